@@ -0,0 +1,662 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use sea_orm::{ActiveValue, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait, Statement, TransactionTrait};
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tonic::codegen::http::{Extensions, Request, Response};
+use tonic::{Code, Status};
+use tower::{BoxError, Layer, Service};
+use entities::api_audit_log;
+use grpc::api_audit::api_audit::api_audit_server::{ApiAudit, SERVICE_NAME as API_AUDIT_SERVICE};
+use grpc::api_audit::api_audit::{AuditLogQueryRequest, AuditLogQueryResponse};
+use grpc::asset_details::asset_details::asset_details_server::{AssetDetails, SERVICE_NAME as ASSET_DETAILS_SERVICE};
+use grpc::asset_details::asset_details::{AssetDetailsCompanyResponse, AssetDetailsRequest};
+use grpc::authentication::Principal;
+use utils::error::{Error, ErrorType};
+use crate::request_metrics::{error_code, status_code, CallGuard};
+
+/// How often partitions are created ahead of time and expired ones dropped
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60 * 60 * 6);
+
+/// Settings for the audit subsystem, loaded from config
+#[derive(Debug, Clone)]
+pub struct AuditSettings {
+    pub enabled: bool,
+    /// Number of records that can be buffered before new records are dropped
+    pub buffer_size: usize,
+    /// Maximum number of records written in a single insert
+    pub batch_size: usize,
+    /// Number of monthly partitions to keep, older partitions are dropped
+    pub retention_months: u32,
+}
+
+/// A single audited call
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub created_at: DateTime<Utc>,
+    pub principal: String,
+    pub auth_method: String,
+    pub method: String,
+    pub request_params: serde_json::Value,
+    pub status_code: Code,
+    pub latency: Duration,
+}
+
+impl AuditRecord {
+    fn into_active_model(self) -> api_audit_log::ActiveModel {
+        api_audit_log::ActiveModel {
+            id: ActiveValue::Set(uuid::Uuid::now_v7()),
+            created_at: ActiveValue::Set(self.created_at.into()),
+            principal: ActiveValue::Set(self.principal),
+            auth_method: ActiveValue::Set(self.auth_method),
+            method: ActiveValue::Set(self.method),
+            request_params: ActiveValue::Set(self.request_params),
+            status_code: ActiveValue::Set(self.status_code as i32),
+            latency_us: ActiveValue::Set(self.latency.as_micros() as i64),
+        }
+    }
+}
+
+/// Handle for recording audit records. Records go through a bounded buffer to a background
+/// writer, recording never waits on the database and drops records when the buffer is full.
+#[derive(Clone)]
+pub struct AuditLogger {
+    sender: Option<mpsc::Sender<AuditRecord>>,
+    dropped: Arc<AtomicU64>,
+    /// The background writer, taken by whichever clone closes the logger
+    writer: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl AuditLogger {
+    /// A logger that discards every record, used when auditing is disabled
+    pub fn disabled() -> Self {
+        Self {
+            sender: None,
+            dropped: Arc::new(AtomicU64::new(0)),
+            writer: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Start the background writer and partition maintenance tasks
+    ///
+    /// # Arguments
+    ///
+    /// * `database_connection` - The database connection to write records to
+    /// * `settings` - The audit settings
+    ///
+    /// # Returns
+    ///
+    /// A logger handle that can be cloned into request handlers
+    pub fn start(database_connection: DatabaseConnection, settings: &AuditSettings) -> Self {
+        if !settings.enabled {
+            tracing::info!("Audit logging is disabled");
+            return Self::disabled();
+        }
+
+        let (sender, receiver) = mpsc::channel::<AuditRecord>(settings.buffer_size);
+
+        let writer = tokio::spawn(run_writer(database_connection.clone(), receiver, settings.batch_size));
+        tokio::spawn(run_maintenance(database_connection, settings.retention_months));
+
+        Self {
+            sender: Some(sender),
+            dropped: Arc::new(AtomicU64::new(0)),
+            writer: Arc::new(Mutex::new(Some(writer))),
+        }
+    }
+
+    /// Wait for the buffered records to be written on shutdown. The writer only stops once every
+    /// clone is dropped, so this is called after the server has stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to wait, records still buffered after it are lost
+    pub async fn close(self, timeout: Duration) {
+        let writer = self.writer.lock().ok().and_then(|mut writer| writer.take());
+        drop(self);
+
+        let Some(writer) = writer else {
+            return;
+        };
+
+        if tokio::time::timeout(timeout, writer).await.is_err() {
+            tracing::warn!("Audit records still buffered after {:?} were dropped", timeout);
+        }
+    }
+
+    /// A logger handing records to the returned receiver instead of the database
+    #[cfg(test)]
+    fn channel(buffer_size: usize) -> (Self, mpsc::Receiver<AuditRecord>) {
+        let (sender, receiver) = mpsc::channel::<AuditRecord>(buffer_size);
+
+        let audit_logger = Self {
+            sender: Some(sender),
+            dropped: Arc::new(AtomicU64::new(0)),
+            writer: Arc::new(Mutex::new(None)),
+        };
+
+        (audit_logger, receiver)
+    }
+
+    /// Record a call, never blocks and never fails the caller
+    pub fn record(&self, record: AuditRecord) {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return,
+        };
+
+        if let Err(e) = sender.try_send(record) {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+
+            // Avoid flooding the logs while the buffer is saturated
+            if dropped == 1 || dropped.is_multiple_of(1000) {
+                tracing::warn!("Dropped audit record ({} dropped in total): {}", dropped, e);
+            }
+        }
+    }
+}
+
+/// Drain the audit buffer into the database in batches until every sender is dropped
+async fn run_writer(database_connection: DatabaseConnection, mut receiver: mpsc::Receiver<AuditRecord>, batch_size: usize) {
+    let mut batch: Vec<AuditRecord> = Vec::with_capacity(batch_size);
+
+    while receiver.recv_many(&mut batch, batch_size).await > 0 {
+        let batch_length = batch.len();
+        let models = batch.drain(..).map(AuditRecord::into_active_model);
+
+        match api_audit_log::Entity::insert_many(models).exec(&database_connection).await {
+            Ok(_) => {
+                tracing::debug!("Wrote {} audit records", batch_length);
+            }
+            Err(e) => {
+                tracing::error!("Failed to write {} audit records: {}", batch_length, e);
+            }
+        }
+    }
+
+    tracing::info!("Audit writer stopped");
+}
+
+/// Periodically create upcoming partitions and drop the ones past retention
+async fn run_maintenance(database_connection: DatabaseConnection, retention_months: u32) {
+    let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let now = Utc::now();
+
+        if let Err(e) = ensure_partitions(&database_connection, now).await {
+            tracing::error!("Failed to create audit log partitions: {}", e);
+        }
+
+        if let Err(e) = drop_expired_partitions(&database_connection, now, retention_months).await {
+            tracing::error!("Failed to apply audit log retention: {}", e);
+        }
+    }
+}
+
+/// Shift a year and month by a number of months
+fn shift_month(year: i32, month: u32, delta: i32) -> (i32, u32) {
+    let index = year * 12 + (month as i32 - 1) + delta;
+
+    (index.div_euclid(12), (index.rem_euclid(12) + 1) as u32)
+}
+
+/// Name of the partition holding a given month
+fn partition_name(year: i32, month: u32) -> String {
+    format!("api_audit_log_y{:04}m{:02}", year, month)
+}
+
+/// Parse the year and month back out of a partition name
+fn parse_partition_name(name: &str) -> Option<(i32, u32)> {
+    let suffix = name.strip_prefix("api_audit_log_y")?;
+    let (year, month) = suffix.split_once('m')?;
+
+    Some((year.parse().ok()?, month.parse().ok()?))
+}
+
+fn first_of_month(year: i32, month: u32) -> Result<NaiveDate, Error> {
+    NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(|| {
+        Error::new(ErrorType::ParseError, format!("Invalid partition month {}-{}", year, month))
+    })
+}
+
+/// Create the partitions for the current and next month if they don't exist yet. Rows already
+/// written to the default partition for a missing month would make creating its partition fail,
+/// so the default partition is detached while they are moved into the new partition.
+///
+/// # Arguments
+///
+/// * `database_connection` - The database connection
+/// * `now` - The current time
+///
+/// # Errors
+///
+/// * If a partition cannot be created, returns a DatabaseError
+pub async fn ensure_partitions(database_connection: &DatabaseConnection, now: DateTime<Utc>) -> Result<(), Error> {
+    let database_error = |e: sea_orm::DbErr| Error::new(ErrorType::DatabaseError, format!("Failed to create partition: {}", e));
+
+    for offset in 0..=1 {
+        let (year, month) = shift_month(now.year(), now.month(), offset);
+        let (next_year, next_month) = shift_month(year, month, 1);
+
+        let name = partition_name(year, month);
+        let from = first_of_month(year, month)?;
+        let to = first_of_month(next_year, next_month)?;
+
+        let exists = database_connection
+            .query_one(Statement::from_string(
+                DbBackend::Postgres,
+                format!("SELECT to_regclass('{}') IS NOT NULL AS exists", name),
+            ))
+            .await
+            .map_err(database_error)?
+            .map(|row| row.try_get::<bool>("", "exists"))
+            .transpose()
+            .map_err(database_error)?
+            .unwrap_or(false);
+
+        if exists {
+            continue;
+        }
+
+        tracing::info!("Creating audit log partition {}", name);
+
+        // DDL is transactional in Postgres, so writers never see the default partition missing
+        let transaction = database_connection.begin().await.map_err(database_error)?;
+
+        for statement in [
+            "ALTER TABLE api_audit_log DETACH PARTITION api_audit_log_default".to_string(),
+            format!("CREATE TABLE IF NOT EXISTS {} PARTITION OF api_audit_log FOR VALUES FROM ('{}') TO ('{}')", name, from, to),
+            format!("INSERT INTO {} SELECT * FROM api_audit_log_default WHERE created_at >= '{}' AND created_at < '{}'", name, from, to),
+            format!("DELETE FROM api_audit_log_default WHERE created_at >= '{}' AND created_at < '{}'", from, to),
+            "ALTER TABLE api_audit_log ATTACH PARTITION api_audit_log_default DEFAULT".to_string(),
+        ] {
+            transaction.execute_unprepared(&statement).await.map_err(database_error)?;
+        }
+
+        transaction.commit().await.map_err(database_error)?;
+    }
+
+    Ok(())
+}
+
+/// Drop monthly partitions that are entirely older than the retention window, and delete expired
+/// rows from the default partition
+///
+/// # Arguments
+///
+/// * `database_connection` - The database connection
+/// * `now` - The current time
+/// * `retention_months` - The number of months to keep, including the current one
+///
+/// # Errors
+///
+/// * If the partitions cannot be listed or dropped, returns a DatabaseError
+pub async fn drop_expired_partitions(database_connection: &DatabaseConnection, now: DateTime<Utc>, retention_months: u32) -> Result<(), Error> {
+    let database_error = |e: sea_orm::DbErr| Error::new(ErrorType::DatabaseError, e.to_string());
+
+    let (cutoff_year, cutoff_month) = shift_month(now.year(), now.month(), -(retention_months as i32 - 1));
+    let cutoff = first_of_month(cutoff_year, cutoff_month)?;
+
+    let partitions = database_connection
+        .query_all(Statement::from_string(
+            DbBackend::Postgres,
+            "SELECT child.relname AS name FROM pg_inherits \
+             JOIN pg_class parent ON parent.oid = pg_inherits.inhparent \
+             JOIN pg_class child ON child.oid = pg_inherits.inhrelid \
+             WHERE parent.relname = 'api_audit_log'",
+        ))
+        .await
+        .map_err(database_error)?;
+
+    for row in partitions {
+        let name: String = row.try_get("", "name").map_err(database_error)?;
+
+        let (year, month) = match parse_partition_name(&name) {
+            Some(partition_month) => partition_month,
+            None => continue,
+        };
+
+        if (year, month) < (cutoff_year, cutoff_month) {
+            tracing::info!("Dropping expired audit log partition {}", name);
+
+            database_connection
+                .execute_unprepared(&format!("DROP TABLE IF EXISTS {}", name))
+                .await
+                .map_err(database_error)?;
+        }
+    }
+
+    database_connection
+        .execute_unprepared(&format!("DELETE FROM api_audit_log_default WHERE created_at < '{}'", cutoff))
+        .await
+        .map_err(database_error)?;
+
+    Ok(())
+}
+
+/// Services whose calls are audited, health checks and reflection are not
+const AUDITED_SERVICES: [&str; 2] = [ASSET_DETAILS_SERVICE, API_AUDIT_SERVICE];
+
+/// Whether calls to a method path, e.g. `/asset_details.AssetDetails/GetCompany`, are audited
+fn is_audited(path: &str) -> bool {
+    path.strip_prefix('/')
+        .and_then(|path| path.split_once('/'))
+        .is_some_and(|(service, _)| AUDITED_SERVICES.contains(&service))
+}
+
+/// What is known about an audited call, filled in by the auth interceptor and the handler as the
+/// call gets to them. Calls rejected before the handler are recorded without request parameters,
+/// and anonymously if no credentials were accepted.
+#[derive(Debug, Default)]
+pub struct AuditContext {
+    principal: Mutex<Option<Principal>>,
+    request_params: Mutex<Option<serde_json::Value>>,
+}
+
+impl AuditContext {
+    /// Record who the call was authenticated as, if the call is audited
+    pub fn set_principal(extensions: &Extensions, principal: &Principal) {
+        if let Some(context) = extensions.get::<Arc<AuditContext>>() {
+            if let Ok(mut slot) = context.principal.lock() {
+                *slot = Some(principal.clone());
+            }
+        }
+    }
+
+    /// Record the parameters of the call, if the call is audited
+    pub fn set_request_params(extensions: &tonic::Extensions, request_params: serde_json::Value) {
+        if let Some(context) = extensions.get::<Arc<AuditContext>>() {
+            if let Ok(mut slot) = context.request_params.lock() {
+                *slot = Some(request_params);
+            }
+        }
+    }
+
+    fn to_record(&self, created_at: DateTime<Utc>, method: String, status_code: Code, latency: Duration) -> AuditRecord {
+        let principal = self.principal.lock().ok().and_then(|mut slot| slot.take());
+        let request_params = self.request_params.lock().ok().and_then(|mut slot| slot.take());
+
+        AuditRecord {
+            created_at,
+            principal: principal.as_ref().map(|p| p.subject.clone()).unwrap_or_else(|| "anonymous".to_string()),
+            auth_method: principal.as_ref().map(|p| p.method.to_string()).unwrap_or_else(|| "none".to_string()),
+            method,
+            request_params: request_params.unwrap_or_else(|| json!({})),
+            status_code,
+            latency,
+        }
+    }
+}
+
+/// Records an audit entry for every call to an audited service once it completes, whatever its
+/// status. Sits outside the auth interceptor so rejected calls are recorded too, and records calls
+/// that time out or are cancelled by the client when their future is dropped.
+#[derive(Clone)]
+pub struct AuditLayer {
+    pub audit_logger: AuditLogger,
+    /// The server's request timeout, to tell timed out calls from cancelled ones
+    pub request_timeout: Duration,
+}
+
+impl<S> Layer<S> for AuditLayer {
+    type Service = Audit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Audit { inner, audit_logger: self.audit_logger.clone(), request_timeout: self.request_timeout }
+    }
+}
+
+#[derive(Clone)]
+pub struct Audit<S> {
+    inner: S,
+    audit_logger: AuditLogger,
+    request_timeout: Duration,
+}
+
+impl<S, RequestBody, ResponseBody> Service<Request<RequestBody>> for Audit<S>
+where
+    S: Service<Request<RequestBody>, Response = Response<ResponseBody>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    RequestBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<RequestBody>) -> Self::Future {
+        if !is_audited(request.uri().path()) {
+            let response = self.inner.call(request);
+            return Box::pin(async move { response.await.map_err(Into::into) });
+        }
+
+        let created_at = Utc::now();
+        let method: String = request.uri().path().to_string();
+
+        let context = Arc::new(AuditContext::default());
+        request.extensions_mut().insert(context.clone());
+
+        let audit_logger = self.audit_logger.clone();
+        let guard = CallGuard::new(request.headers(), self.request_timeout, move |status_code: Code, latency: Duration| {
+            audit_logger.record(context.to_record(created_at, method, status_code, latency));
+        });
+
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await.map_err(Into::into);
+
+            guard.finish(match &response {
+                Ok(response) => status_code(response.headers()),
+                Err(error) => error_code(error),
+            });
+
+            response
+        })
+    }
+}
+
+/// Wraps an AssetDetails implementation to add the requested symbol to the audit entry
+pub struct AuditedAssetDetails<S: AssetDetails> {
+    pub inner: S,
+}
+
+#[tonic::async_trait]
+impl<S: AssetDetails> AssetDetails for AuditedAssetDetails<S> {
+    async fn get_company(
+        &self,
+        request: tonic::Request<AssetDetailsRequest>,
+    ) -> Result<tonic::Response<AssetDetailsCompanyResponse>, Status> {
+        AuditContext::set_request_params(request.extensions(), json!({ "symbol": request.get_ref().symbol }));
+
+        self.inner.get_company(request).await
+    }
+}
+
+/// Wraps an ApiAudit implementation to add the query to the audit entry, so reads of the audit
+/// log are audited too
+pub struct AuditedApiAudit<S: ApiAudit> {
+    pub inner: S,
+}
+
+#[tonic::async_trait]
+impl<S: ApiAudit> ApiAudit for AuditedApiAudit<S> {
+    async fn query_audit_log(
+        &self,
+        request: tonic::Request<AuditLogQueryRequest>,
+    ) -> Result<tonic::Response<AuditLogQueryResponse>, Status> {
+        let query = request.get_ref();
+        let request_params = json!({
+            "start": query.start,
+            "end": query.end,
+            "principal": query.principal,
+            "symbol": query.symbol,
+            "method": query.method,
+            "limit": query.limit,
+        });
+
+        AuditContext::set_request_params(request.extensions(), request_params);
+
+        self.inner.query_audit_log(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth_interceptor::{AuthInterceptor, Authenticator};
+    use grpc::api_audit::api_audit::api_audit_client::ApiAuditClient;
+    use grpc::api_audit::api_audit::api_audit_server::ApiAuditServer;
+    use grpc::api_audit::ApiAuditService;
+    use grpc::authentication::AuthMethod;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::{Channel, Server};
+    use tonic_health::pb::health_client::HealthClient;
+    use tonic_health::pb::HealthCheckRequest;
+    use tonic_middleware::InterceptorFor;
+
+    #[test]
+    fn test_shift_month() {
+        assert_eq!(shift_month(2024, 12, 1), (2025, 1));
+        assert_eq!(shift_month(2024, 1, -1), (2023, 12));
+        assert_eq!(shift_month(2024, 6, -11), (2023, 7));
+        assert_eq!(shift_month(2024, 6, 0), (2024, 6));
+    }
+
+    #[test]
+    fn test_partition_name_round_trip() {
+        let name = partition_name(2024, 3);
+
+        assert_eq!(name, "api_audit_log_y2024m03");
+        assert_eq!(parse_partition_name(&name), Some((2024, 3)));
+        assert_eq!(parse_partition_name("api_audit_log_default"), None);
+    }
+
+    #[test]
+    fn test_is_audited() {
+        assert!(is_audited("/asset_details.AssetDetails/GetCompany"));
+        assert!(is_audited("/api_audit.ApiAudit/QueryAuditLog"));
+        assert!(!is_audited("/grpc.health.v1.Health/Check"));
+    }
+
+    /// Accepts any call carrying an `authorization` header as a caller without permissions
+    struct AnyCaller;
+
+    #[tonic::async_trait]
+    impl Authenticator for AnyCaller {
+        async fn authenticate(&self, headers: &tonic::codegen::http::HeaderMap, _extensions: &Extensions) -> Result<Option<Principal>, Status> {
+            Ok(headers.get("authorization").map(|_| Principal {
+                subject: "tester".to_string(),
+                method: AuthMethod::Bearer,
+                permissions: vec![],
+            }))
+        }
+    }
+
+    /// Accepts every call, but only after a delay
+    struct SlowCaller;
+
+    #[tonic::async_trait]
+    impl Authenticator for SlowCaller {
+        async fn authenticate(&self, _headers: &tonic::codegen::http::HeaderMap, _extensions: &Extensions) -> Result<Option<Principal>, Status> {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_calls_are_audited() {
+        let (audit_logger, mut records) = AuditLogger::channel(16);
+
+        let api_audit_server = ApiAuditServer::new(AuditedApiAudit {
+            inner: ApiAuditService { database_connection: DatabaseConnection::Disconnected },
+        });
+        let auth_interceptor = AuthInterceptor { authenticators: Arc::new(vec![Box::new(SlowCaller)]) };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(Server::builder()
+            .timeout(Duration::from_secs(5))
+            .layer(AuditLayer { audit_logger, request_timeout: Duration::from_secs(5) })
+            .add_service(InterceptorFor::new(api_audit_server, auth_interceptor))
+            .serve_with_incoming(incoming));
+
+        let channel = Channel::from_shared(format!("http://{}", address)).unwrap().connect().await.unwrap();
+        let mut client = ApiAuditClient::new(channel);
+
+        // tonic drops the call once the client's grpc-timeout passes
+        let mut request = tonic::Request::new(AuditLogQueryRequest::default());
+        request.set_timeout(Duration::from_millis(50));
+        let timed_out = client.query_audit_log(request).await.unwrap_err();
+        assert_eq!(timed_out.code(), Code::Cancelled);
+
+        let record = records.recv().await.unwrap();
+        assert_eq!(record.method, "/api_audit.ApiAudit/QueryAuditLog");
+        assert_eq!(record.status_code, Code::DeadlineExceeded);
+        assert!(record.latency < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_every_status_is_audited() {
+        let (audit_logger, mut records) = AuditLogger::channel(16);
+        let (_reporter, health_service) = tonic_health::server::health_reporter();
+
+        let api_audit_server = ApiAuditServer::new(AuditedApiAudit {
+            inner: ApiAuditService { database_connection: DatabaseConnection::Disconnected },
+        });
+        let auth_interceptor = AuthInterceptor { authenticators: Arc::new(vec![Box::new(AnyCaller)]) };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(Server::builder()
+            .layer(AuditLayer { audit_logger, request_timeout: Duration::from_secs(5) })
+            .add_service(health_service)
+            .add_service(InterceptorFor::new(api_audit_server, auth_interceptor))
+            .serve_with_incoming(incoming));
+
+        let channel = Channel::from_shared(format!("http://{}", address)).unwrap().connect().await.unwrap();
+        let mut client = ApiAuditClient::new(channel.clone());
+        let query = AuditLogQueryRequest { symbol: Some("AAPL".to_string()), ..Default::default() };
+
+        // Rejected by the interceptor, before the handler could add the query
+        let rejected = client.query_audit_log(query.clone()).await.unwrap_err();
+        assert_eq!(rejected.code(), Code::Unauthenticated);
+
+        let record = records.recv().await.unwrap();
+        assert_eq!(record.method, "/api_audit.ApiAudit/QueryAuditLog");
+        assert_eq!(record.status_code, Code::Unauthenticated);
+        assert_eq!(record.principal, "anonymous");
+        assert_eq!(record.request_params, json!({}));
+
+        // Authenticated but without the audit:read permission
+        let mut request = tonic::Request::new(query);
+        request.metadata_mut().insert("authorization", "Bearer token".parse().unwrap());
+        let denied = client.query_audit_log(request).await.unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+
+        let record = records.recv().await.unwrap();
+        assert_eq!(record.status_code, Code::PermissionDenied);
+        assert_eq!(record.principal, "tester");
+        assert_eq!(record.auth_method, "bearer");
+        assert_eq!(record.request_params["symbol"], "AAPL");
+
+        // Health checks are not audited
+        HealthClient::new(channel).check(HealthCheckRequest { service: String::new() }).await.unwrap();
+        assert!(records.try_recv().is_err());
+    }
+}
@@ -1,15 +1,26 @@
 use std::sync::Arc;
+use std::time::Instant;
+use chrono::Utc;
+use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use sha2::{Digest, Sha256};
 use tonic::{async_trait, Status};
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
 use tonic_middleware::RequestInterceptor;
+use tracing::Instrument;
+use x509_parser::prelude::{FromDer, X509Certificate};
 
 use tonic::body::BoxBody;
-use tonic::codegen::http::{Request};
-use grpc::authentication::check_auth;
+use tonic::codegen::http::{Extensions, HeaderMap, Request};
+use entities::api_key;
+use grpc::authentication::{check_auth, AuthMethod, Principal};
+use grpc::request_id::REQUEST_ID_HEADER;
+use crate::audit::AuditContext;
+use utils::error::{Error, ErrorType};
 
 
 #[async_trait]
 pub trait AuthService: Send + Sync {
-    async fn verify_token(&self, token: &str) -> Result<(), String>;
+    async fn verify_token(&self, token: &str, request_id: Option<&str>) -> Result<Principal, String>;
 }
 
 #[derive(Clone)]
@@ -25,43 +36,387 @@ impl AuthServiceImpl {
 
 #[async_trait]
 impl AuthService for AuthServiceImpl {
-    async fn verify_token(&self, token: &str) -> Result<(), String> {
+    async fn verify_token(&self, token: &str, request_id: Option<&str>) -> Result<Principal, String> {
         let auth_url = self.auth_url.clone();
-        check_auth(auth_url, token).await.map_err(|e| e.to_string())
+        let token_data = check_auth(auth_url, token, request_id).await.map_err(|e| e.to_string())?;
+
+        Ok(Principal::from_token_data(token_data))
     }
 }
 
-#[derive(Clone)]
-pub struct AuthInterceptor<A: AuthService> {
+/// A single authentication mechanism in the interceptor chain. Authenticators receive the request
+/// headers and extensions rather than the request itself, as the request body is not `Sync`.
+///
+/// Returning `Ok(None)` means the request carries no credentials for this mechanism and the next
+/// authenticator in the chain should be tried. Returning an error means credentials were
+/// presented but are invalid, which rejects the request outright.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    async fn authenticate(&self, headers: &HeaderMap, extensions: &Extensions) -> Result<Option<Principal>, Status>;
+}
+
+/// Parse the ordered authenticator chain from a comma separated config value
+///
+/// # Arguments
+///
+/// * `raw_chain` - The raw config value, e.g. `api_key,client_cert,bearer`
+///
+/// # Returns
+///
+/// The ordered list of authentication methods
+///
+/// # Errors
+///
+/// * If the chain is empty or contains an unknown authenticator, returns an InvalidConfig error
+pub fn parse_auth_chain(raw_chain: &str) -> Result<Vec<AuthMethod>, Error> {
+    let mut chain: Vec<AuthMethod> = Vec::new();
+
+    for name in raw_chain.split(',').map(|value| value.trim()).filter(|value| !value.is_empty()) {
+        let method = match name.to_lowercase().as_str() {
+            "bearer" => AuthMethod::Bearer,
+            "api_key" => AuthMethod::ApiKey,
+            "client_cert" => AuthMethod::ClientCertificate,
+            _ => {
+                return Err(Error::new(ErrorType::InvalidConfig, format!("Unknown authenticator: {}", name)));
+            }
+        };
+
+        if !chain.contains(&method) {
+            chain.push(method);
+        }
+    }
+
+    if chain.is_empty() {
+        return Err(Error::new(ErrorType::InvalidConfig, "At least one authenticator must be configured".to_string()));
+    }
+
+    Ok(chain)
+}
+
+/// Hash a raw API key for storage and lookup, only the hash is ever persisted
+///
+/// # Arguments
+///
+/// * `raw_key` - The API key as presented by the client
+///
+/// # Returns
+///
+/// The hex encoded SHA-256 digest of the key
+pub fn hash_api_key(raw_key: &str) -> String {
+    hex::encode(Sha256::digest(raw_key.as_bytes()))
+}
+
+/// Authenticates `authorization: Bearer <token>` headers against the authentication service
+pub struct BearerAuthenticator<A: AuthService> {
     pub auth_service: Arc<A>,
 }
 
 #[async_trait]
-impl<A: AuthService> RequestInterceptor for AuthInterceptor<A> {
-    async fn intercept(&self, req: Request<BoxBody>) -> Result<Request<BoxBody>, Status> {
-        match req.headers().get("authorization").map(|v| v.to_str()) {
-            Some(Ok(header_data)) => {
-                let parse_token = header_data.split_whitespace().collect::<Vec<&str>>();
+impl<A: AuthService> Authenticator for BearerAuthenticator<A> {
+    async fn authenticate(&self, headers: &HeaderMap, _extensions: &Extensions) -> Result<Option<Principal>, Status> {
+        let header_data = match headers.get("authorization").map(|v| v.to_str()) {
+            Some(Ok(header_data)) => header_data,
+            Some(Err(_)) => return Err(Status::unauthenticated("Unauthenticated")),
+            None => return Ok(None),
+        };
+
+        let parse_token = header_data.split_whitespace().collect::<Vec<&str>>();
+
+        if parse_token.len() != 2 {
+            return Err(Status::unauthenticated("Unauthenticated"));
+        }
 
-                if parse_token.len() != 2 {
-                    return Err(Status::unauthenticated("Unauthenticated"));
-                }
+        let token = parse_token[1];
 
-                let token = parse_token[1];
+        tracing::info!("Verifying token");
 
-                tracing::info!("Verifying token");
+        // Verify the token using the auth service, under the ID the request middleware assigned
+        let request_id = headers.get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok());
 
-                // Verify the token using the auth service
-                self.auth_service.verify_token(token).await.map_err(|e| {
-                    tracing::error!("Error verifying token: {}", e);
-                    Status::unauthenticated("Unauthenticated")
-                })?;
+        let principal = self.auth_service.verify_token(token, request_id).await.map_err(|e| {
+            tracing::error!("Error verifying token: {}", e);
+            Status::unauthenticated("Unauthenticated")
+        })?;
 
-                tracing::info!("Token verified");
+        tracing::info!("Token verified");
 
-                Ok(req)
+        Ok(Some(principal))
+    }
+}
+
+/// Authenticates `x-api-key` headers against the hashed keys stored in the `api_key` table
+pub struct ApiKeyAuthenticator {
+    pub database_connection: DatabaseConnection,
+}
+
+#[async_trait]
+impl Authenticator for ApiKeyAuthenticator {
+    async fn authenticate(&self, headers: &HeaderMap, _extensions: &Extensions) -> Result<Option<Principal>, Status> {
+        let raw_key = match headers.get("x-api-key").map(|v| v.to_str()) {
+            Some(Ok(raw_key)) => raw_key,
+            Some(Err(_)) => return Err(Status::unauthenticated("Unauthenticated")),
+            None => return Ok(None),
+        };
+
+        let key_hash = hash_api_key(raw_key);
+
+        let stored_key = api_key::Entity::find()
+            .filter(api_key::Column::KeyHash.eq(key_hash))
+            .one(&self.database_connection)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to look up API key: {}", e);
+                Status::unavailable("Unable to verify API key")
+            })?;
+
+        let stored_key = match stored_key {
+            Some(stored_key) => stored_key,
+            None => {
+                tracing::warn!("Unknown API key presented");
+                return Err(Status::unauthenticated("Unauthenticated"));
             }
-            _ => Err(Status::unauthenticated("Unauthenticated")),
+        };
+
+        if let Some(expires_at) = stored_key.expires_at {
+            if expires_at <= Utc::now() {
+                tracing::warn!("Expired API key presented: {}", stored_key.name);
+                return Err(Status::unauthenticated("Unauthenticated"));
+            }
+        }
+
+        // Recording last use is best effort and must not hold up the request
+        let database_connection = self.database_connection.clone();
+        let key_id = stored_key.id;
+        tokio::spawn(async move {
+            let update = api_key::ActiveModel {
+                id: ActiveValue::Unchanged(key_id),
+                last_used_at: ActiveValue::Set(Some(Utc::now().into())),
+                ..Default::default()
+            };
+
+            if let Err(e) = api_key::Entity::update(update).exec(&database_connection).await {
+                tracing::error!("Failed to record API key usage: {}", e);
+            }
+        });
+
+        tracing::info!("API key verified: {}", stored_key.name);
+
+        Ok(Some(Principal {
+            subject: stored_key.name,
+            method: AuthMethod::ApiKey,
+            permissions: stored_key.scopes,
+        }))
+    }
+}
+
+/// Authenticates callers by the identity (subject common name) of the client certificate they
+/// presented during the TLS handshake. This only yields a principal when the server is running
+/// with TLS client auth, as the certificate chain itself is verified by the TLS layer.
+pub struct ClientCertAuthenticator {
+    /// If non-empty, only these identities are accepted
+    pub allowed_identities: Vec<String>,
+}
+
+/// Extract the subject common name from a DER encoded certificate
+///
+/// # Arguments
+///
+/// * `der` - The DER encoded certificate
+///
+/// # Returns
+///
+/// The first common name of the certificate subject
+///
+/// # Errors
+///
+/// * If the certificate cannot be parsed or has no common name, returns a ParseError
+pub fn certificate_identity(der: &[u8]) -> Result<String, Error> {
+    let (_, certificate) = X509Certificate::from_der(der).map_err(|e| {
+        Error::new(ErrorType::ParseError, format!("Failed to parse client certificate: {}", e))
+    })?;
+
+    let common_name = certificate.subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .ok_or_else(|| Error::new(ErrorType::ParseError, "Client certificate has no common name".to_string()))?;
+
+    Ok(common_name.to_string())
+}
+
+#[async_trait]
+impl Authenticator for ClientCertAuthenticator {
+    async fn authenticate(&self, _headers: &HeaderMap, extensions: &Extensions) -> Result<Option<Principal>, Status> {
+        let peer_certs = extensions
+            .get::<TlsConnectInfo<TcpConnectInfo>>()
+            .and_then(|info| info.peer_certs());
+
+        let leaf_certificate = match peer_certs.as_ref().and_then(|certs| certs.first()) {
+            Some(certificate) => certificate,
+            None => return Ok(None),
+        };
+
+        let identity = certificate_identity(leaf_certificate.as_ref()).map_err(|e| {
+            tracing::error!("Error reading client certificate: {}", e);
+            Status::unauthenticated("Unauthenticated")
+        })?;
+
+        if !self.allowed_identities.is_empty() && !self.allowed_identities.contains(&identity) {
+            tracing::warn!("Client certificate identity not allowed: {}", identity);
+            return Err(Status::unauthenticated("Unauthenticated"));
         }
+
+        tracing::info!("Client certificate verified: {}", identity);
+
+        Ok(Some(Principal {
+            subject: identity,
+            method: AuthMethod::ClientCertificate,
+            permissions: vec![],
+        }))
+    }
+}
+
+/// gRPC middleware that runs the configured authenticators in order. The first authenticator to
+/// recognise credentials on the request decides the outcome, and the resulting principal is
+/// inserted into the request extensions.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    pub authenticators: Arc<Vec<Box<dyn Authenticator>>>,
+}
+
+/// Record how long authenticating a request took and its outcome, failures are also counted apart.
+/// The outcome is also set on the current `auth.check` span.
+fn record_auth(outcome: &'static str, started_at: Instant) {
+    metrics::histogram!("api_auth_duration_seconds", "outcome" => outcome).record(started_at.elapsed());
+    tracing::Span::current().record("auth.outcome", outcome);
+
+    if outcome != "authenticated" {
+        metrics::counter!("api_auth_failures_total", "reason" => outcome).increment(1);
+    }
+}
+
+impl AuthInterceptor {
+    async fn authenticate(&self, mut req: Request<BoxBody>) -> Result<Request<BoxBody>, Status> {
+        let started_at = Instant::now();
+
+        for authenticator in self.authenticators.iter() {
+            let authenticated = authenticator.authenticate(req.headers(), req.extensions()).await
+                .inspect_err(|_| record_auth("rejected", started_at))?;
+
+            if let Some(principal) = authenticated {
+                tracing::debug!("Authenticated {} via {}", principal.subject, principal.method);
+                tracing::Span::current().record("auth.method", principal.method.as_str());
+                record_auth("authenticated", started_at);
+
+                AuditContext::set_principal(req.extensions(), &principal);
+                req.extensions_mut().insert(principal);
+
+                return Ok(req);
+            }
+        }
+
+        record_auth("missing_credentials", started_at);
+
+        Err(Status::unauthenticated("Unauthenticated"))
+    }
+}
+
+#[async_trait]
+impl RequestInterceptor for AuthInterceptor {
+    async fn intercept(&self, req: Request<BoxBody>) -> Result<Request<BoxBody>, Status> {
+        let span = tracing::info_span!("auth.check", auth.method = tracing::field::Empty, auth.outcome = tracing::field::Empty);
+
+        self.authenticate(req).instrument(span).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grpc::authentication::authentication::authentication_server::AuthenticationServer;
+    use mock_auth::{parse_tokens, MockAuthService, MockMode};
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+
+    /// Start a mock authentication server on a random local port and return its URL
+    async fn start_mock_auth(mode: MockMode) -> String {
+        let tokens = parse_tokens(r#"{"tokens": [{"token": "valid-token", "sub": "tester", "permissions": ["audit:read"]}]}"#).unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+
+        let mock_auth_service = MockAuthService {
+            mode,
+            tokens,
+            default_token: Default::default(),
+        };
+
+        tokio::spawn(Server::builder()
+            .add_service(AuthenticationServer::new(mock_auth_service))
+            .serve_with_incoming(incoming));
+
+        format!("http://{}", address)
+    }
+
+    fn bearer_interceptor(auth_url: String) -> AuthInterceptor {
+        AuthInterceptor {
+            authenticators: Arc::new(vec![Box::new(BearerAuthenticator {
+                auth_service: Arc::new(AuthServiceImpl::new(auth_url)),
+            })]),
+        }
+    }
+
+    fn request_with_token(token: &str) -> Request<BoxBody> {
+        Request::builder()
+            .header("authorization", format!("Bearer {}", token))
+            .body(tonic::body::empty_body())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bearer_chain_against_mock_auth() {
+        let interceptor = bearer_interceptor(start_mock_auth(MockMode::File).await);
+
+        let accepted = interceptor.intercept(request_with_token("valid-token")).await.unwrap();
+        let principal = accepted.extensions().get::<Principal>().unwrap();
+        assert_eq!(principal.subject, "tester");
+        assert_eq!(principal.method, AuthMethod::Bearer);
+        assert_eq!(principal.permissions, vec!["audit:read".to_string()]);
+
+        let rejected = interceptor.intercept(request_with_token("invalid-token")).await;
+        assert_eq!(rejected.unwrap_err().code(), tonic::Code::Unauthenticated);
+
+        let missing = interceptor.intercept(Request::builder().body(tonic::body::empty_body()).unwrap()).await;
+        assert_eq!(missing.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_bearer_chain_against_denying_mock_auth() {
+        let interceptor = bearer_interceptor(start_mock_auth(MockMode::Deny).await);
+
+        let rejected = interceptor.intercept(request_with_token("valid-token")).await;
+        assert_eq!(rejected.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_parse_auth_chain() {
+        let chain = parse_auth_chain("api_key, client_cert,bearer").unwrap();
+        assert_eq!(chain, vec![AuthMethod::ApiKey, AuthMethod::ClientCertificate, AuthMethod::Bearer]);
+
+        let deduplicated = parse_auth_chain("bearer,bearer").unwrap();
+        assert_eq!(deduplicated, vec![AuthMethod::Bearer]);
+
+        assert!(parse_auth_chain("").is_err());
+        assert!(parse_auth_chain("bearer,password").is_err());
+    }
+
+    #[test]
+    fn test_hash_api_key() {
+        let hashed = hash_api_key("secret-key");
+
+        assert_eq!(hashed.len(), 64);
+        assert_eq!(hashed, hash_api_key("secret-key"));
+        assert_ne!(hashed, hash_api_key("other-key"));
     }
 }
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use futures::future::join_all;
+use sea_orm::DatabaseConnection;
+use tonic::async_trait;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+use ::config::Shutdown;
+use grpc::asset_details::asset_details::asset_details_server::SERVICE_NAME as ASSET_DETAILS_SERVICE;
+use grpc::authentication::ping_auth;
+use utils::cache::Cache;
+
+/// Reported SERVING for as long as the process runs, for liveness probes, so a replica is never
+/// restarted because a dependency is down. Readiness probes check the overall status instead.
+pub const LIVENESS_SERVICE: &str = "liveness";
+
+/// The overall status (the empty service name) and AssetDetails follow readiness
+const READINESS_SERVICES: [&str; 2] = ["", ASSET_DETAILS_SERVICE];
+
+/// A dependency probed by the [`HealthChecker`]
+#[async_trait]
+pub trait Probe: Send + Sync {
+    /// Name of the dependency in logs and the `api_dependency_up` metric
+    fn name(&self) -> &'static str;
+
+    /// Whether AssetDetails can't be served while the dependency is down
+    fn critical(&self) -> bool;
+
+    async fn check(&self) -> Result<(), String>;
+}
+
+/// The Postgres pool company lookups are read from, not critical when stale cached copies are
+/// served while it is down
+pub struct PostgresProbe {
+    pub database_connection: DatabaseConnection,
+    pub critical: bool,
+}
+
+#[async_trait]
+impl Probe for PostgresProbe {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn critical(&self) -> bool {
+        self.critical
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.database_connection.ping().await.map_err(|e| e.to_string())
+    }
+}
+
+/// The cache, not critical as calls fall back to Postgres while it is down
+pub struct CacheProbe {
+    pub cache: Arc<dyn Cache>,
+}
+
+#[async_trait]
+impl Probe for CacheProbe {
+    fn name(&self) -> &'static str {
+        "cache"
+    }
+
+    fn critical(&self) -> bool {
+        false
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.cache.ping().await.map_err(|e| e.to_string())
+    }
+}
+
+/// The auth service, only probed when the bearer authenticator is in the chain
+pub struct AuthProbe {
+    pub auth_url: String,
+}
+
+#[async_trait]
+impl Probe for AuthProbe {
+    fn name(&self) -> &'static str {
+        "auth"
+    }
+
+    fn critical(&self) -> bool {
+        true
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        ping_auth(self.auth_url.clone()).await.map_err(|e| e.to_string())
+    }
+}
+
+/// Probes the API's dependencies in the background and reports readiness through the gRPC health
+/// service, so `Check` and `Watch` calls reflect whether calls can actually be served
+pub struct HealthChecker {
+    reporter: HealthReporter,
+    probes: Vec<Box<dyn Probe>>,
+    interval: Duration,
+    timeout: Duration,
+    /// The readiness last reported
+    ready: bool,
+    /// Whether each dependency was up at the last probe, to only log changes
+    up: HashMap<&'static str, bool>,
+}
+
+impl HealthChecker {
+    /// Report the process live but not ready, until [`HealthChecker::run`] finds every critical
+    /// dependency up
+    ///
+    /// # Arguments
+    ///
+    /// * `reporter` - The health service's reporter
+    /// * `probes` - The dependencies to probe
+    /// * `interval` - How often dependencies are probed
+    /// * `timeout` - How long a single probe may take before it counts as failed
+    pub async fn new(mut reporter: HealthReporter, probes: Vec<Box<dyn Probe>>, interval: Duration, timeout: Duration) -> Self {
+        reporter.set_service_status(LIVENESS_SERVICE, ServingStatus::Serving).await;
+
+        for service in READINESS_SERVICES {
+            reporter.set_service_status(service, ServingStatus::NotServing).await;
+        }
+
+        HealthChecker {
+            reporter,
+            probes,
+            interval,
+            timeout,
+            ready: false,
+            up: HashMap::new(),
+        }
+    }
+
+    async fn set_ready(&mut self, ready: bool) {
+        if ready == self.ready {
+            return;
+        }
+
+        let status = if ready { ServingStatus::Serving } else { ServingStatus::NotServing };
+
+        for service in READINESS_SERVICES {
+            self.reporter.set_service_status(service, status).await;
+        }
+
+        tracing::info!("Reporting {:?}", status);
+        self.ready = ready;
+    }
+
+    /// Probe every dependency at once and report whether the critical ones are all up
+    async fn check(&mut self) {
+        let timeout = self.timeout;
+
+        let results = join_all(self.probes.iter().map(|probe| async move {
+            let result = tokio::time::timeout(timeout, probe.check()).await
+                .unwrap_or_else(|_| Err(format!("timed out after {:?}", timeout)));
+
+            (probe.name(), probe.critical(), result)
+        })).await;
+
+        let mut ready = true;
+
+        for (name, critical, result) in results {
+            metrics::gauge!("api_dependency_up", "dependency" => name).set(if result.is_ok() { 1.0 } else { 0.0 });
+
+            match (&result, self.up.insert(name, result.is_ok())) {
+                (Ok(_), Some(false)) => tracing::info!("Dependency {} is reachable again", name),
+                (Err(e), Some(true) | None) => tracing::warn!("Dependency {} is unreachable: {}", name, e),
+                _ => {}
+            }
+
+            if critical && result.is_err() {
+                ready = false;
+            }
+        }
+
+        self.set_ready(ready).await;
+    }
+
+    /// Probe dependencies until shutdown, then report not ready for good so load balancers stop
+    /// routing calls here while the server drains
+    ///
+    /// # Arguments
+    ///
+    /// * `shutdown` - Stops the checker
+    pub async fn run(mut self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(self.interval);
+
+        let checking = async {
+            loop {
+                interval.tick().await;
+                self.check().await;
+            }
+        };
+
+        tokio::select! {
+            _ = checking => {}
+            _ = shutdown.wait() => {}
+        }
+
+        self.set_ready(false).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::{Channel, Server};
+    use tonic_health::pb::health_check_response::ServingStatus as Status;
+    use tonic_health::pb::health_client::HealthClient;
+    use tonic_health::pb::HealthCheckRequest;
+
+    struct FakeProbe {
+        name: &'static str,
+        critical: bool,
+        up: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Probe for FakeProbe {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn critical(&self) -> bool {
+            self.critical
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            match self.up.load(Ordering::SeqCst) {
+                true => Ok(()),
+                false => Err("connection refused".to_string()),
+            }
+        }
+    }
+
+    fn request(service: &str) -> HealthCheckRequest {
+        HealthCheckRequest { service: service.to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_readiness_follows_critical_dependencies() {
+        let (reporter, health_service) = tonic_health::server::health_reporter();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(Server::builder().add_service(health_service).serve_with_incoming(incoming));
+
+        let database_up = Arc::new(AtomicBool::new(true));
+        let probes: Vec<Box<dyn Probe>> = vec![
+            Box::new(FakeProbe { name: "postgres", critical: true, up: database_up.clone() }),
+            Box::new(FakeProbe { name: "cache", critical: false, up: Arc::new(AtomicBool::new(false)) }),
+        ];
+
+        let checker = HealthChecker::new(reporter, probes, Duration::from_millis(10), Duration::from_secs(1)).await;
+
+        let channel = Channel::from_shared(format!("http://{}", address)).unwrap().connect().await.unwrap();
+        let mut client = HealthClient::new(channel);
+        let mut watch = client.watch(request(ASSET_DETAILS_SERVICE)).await.unwrap().into_inner();
+        assert_eq!(watch.message().await.unwrap().unwrap().status(), Status::NotServing);
+
+        let shutdown = Shutdown::default();
+        tokio::spawn(checker.run(shutdown.clone()));
+
+        // The cache being down doesn't stop calls being served
+        assert_eq!(watch.message().await.unwrap().unwrap().status(), Status::Serving);
+
+        database_up.store(false, Ordering::SeqCst);
+        assert_eq!(watch.message().await.unwrap().unwrap().status(), Status::NotServing);
+
+        let overall = client.check(request("")).await.unwrap().into_inner();
+        assert_eq!(overall.status(), Status::NotServing);
+        let liveness = client.check(request(LIVENESS_SERVICE)).await.unwrap().into_inner();
+        assert_eq!(liveness.status(), Status::Serving);
+
+        database_up.store(true, Ordering::SeqCst);
+        assert_eq!(watch.message().await.unwrap().unwrap().status(), Status::Serving);
+
+        shutdown.trigger();
+        assert_eq!(watch.message().await.unwrap().unwrap().status(), Status::NotServing);
+    }
+}
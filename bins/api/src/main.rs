@@ -1,18 +1,106 @@
+mod audit;
 mod config;
 mod auth_interceptor;
+mod health;
+mod reflection;
+mod request_id;
+mod request_metrics;
+mod tls;
 
-use crate::auth_interceptor::{AuthInterceptor, AuthServiceImpl};
-use crate::config::ApiState;
+use crate::auth_interceptor::{ApiKeyAuthenticator, AuthInterceptor, AuthServiceImpl, Authenticator, BearerAuthenticator, ClientCertAuthenticator};
+use crate::audit::{AuditLayer, AuditLogger, AuditedApiAudit, AuditedAssetDetails};
+use crate::config::{ApiConfig, ApiState};
+use crate::health::{AuthProbe, CacheProbe, HealthChecker, PostgresProbe, Probe};
+use crate::reflection::{reflection_service_v1, reflection_service_v1alpha, ReflectionMode};
+use crate::request_id::RequestIdLayer;
+use crate::request_metrics::RequestMetricsLayer;
+use ::config::{init_observability, load_config, load_migrate_config, redacted, run_migrate_command, shutdown_observability, split_command, MigrateConfig, Shutdown};
+use crate::tls::ReloadingTlsAcceptor;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tonic::transport::{Server};
 use tonic_middleware::InterceptorFor;
 use tower::ServiceBuilder;
+use grpc::api_audit::api_audit::api_audit_server::ApiAuditServer;
 use grpc::asset_details::asset_details::asset_details_server::AssetDetailsServer;
+use grpc::asset_details::AssetDetailsService;
+use grpc::authentication::AuthMethod;
+
+/// How long buffered audit records get to be written once the server has stopped
+const AUDIT_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a call may run before tonic cancels it, shortened by the client's `grpc-timeout`
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Exit code of a malformed `migrate` subcommand, EX_USAGE
+const USAGE_EXIT_CODE: i32 = 64;
+
+/// Exit code of an invalid config, EX_CONFIG
+const CONFIG_EXIT_CODE: i32 = 78;
+
+/// Build the ordered authenticator chain from config
+fn build_authenticators(app_state: &ApiState) -> Vec<Box<dyn Authenticator>> {
+    app_state.auth_chain.iter().map(|method| -> Box<dyn Authenticator> {
+        match method {
+            AuthMethod::Bearer => Box::new(BearerAuthenticator {
+                auth_service: Arc::new(AuthServiceImpl::new(app_state.auth_url.clone())),
+            }),
+            AuthMethod::ApiKey => Box::new(ApiKeyAuthenticator {
+                database_connection: app_state.global_state.database_client.clone(),
+            }),
+            AuthMethod::ClientCertificate => Box::new(ClientCertAuthenticator {
+                allowed_identities: app_state.client_cert_identities.clone(),
+            }),
+        }
+    }).collect()
+}
+
+/// The span covering a whole call, continuing the caller's trace when it sent a `traceparent`.
+/// The request ID is recorded by [`RequestIdLayer`], which may have to generate it.
+fn request_span(request: &tonic::codegen::http::Request<()>) -> tracing::Span {
+    let span = tracing::info_span!(
+        "grpc.request",
+        otel.name = %request.uri().path(),
+        otel.kind = "server",
+        rpc.system = "grpc",
+        rpc.method = %request.uri().path(),
+        request_id = tracing::field::Empty,
+    );
+
+    grpc::trace_context::set_parent_from_headers(&span, request.headers());
+
+    span
+}
+
+/// Logging isn't set up until the config is loaded, so config errors go straight to stderr
+fn invalid_config<T>(e: utils::error::Error) -> T {
+    eprintln!("{}", e.message);
+    std::process::exit(CONFIG_EXIT_CODE);
+}
 
 async fn start_server() -> Result<(), Box<dyn std::error::Error>> {
-    let app_state: ApiState = config::load_state().await?;
+    // Logging isn't set up until the config is loaded, so usage errors go straight to stderr
+    let (migrate_command, args) = split_command(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e.message);
+        std::process::exit(USAGE_EXIT_CODE);
+    });
+
+    if let Some(migrate_command) = migrate_command {
+        let migrate_config: MigrateConfig = load_migrate_config::<ApiConfig>(args).unwrap_or_else(invalid_config);
+        init_observability(&migrate_config.rust_log, &migrate_config.otel);
+
+        return Ok(run_migrate_command(&migrate_config.database, migrate_command).await?);
+    }
+
+    let api_config: ApiConfig = load_config(args).unwrap_or_else(invalid_config);
+    init_observability(&api_config.global.rust_log, &api_config.global.otel);
+    tracing::info!(config = %redacted(&api_config), "Loaded configuration");
+
+    // Listening before connecting, so a rollout that stops a replica still starting up is honoured
+    let shutdown = Shutdown::listen();
+
+    let app_state: ApiState = config::load_state(api_config).await?;
 
     let server_address: String = format!("{}:{}", &app_state.address, &app_state.port);
 
@@ -20,45 +108,183 @@ async fn start_server() -> Result<(), Box<dyn std::error::Error>> {
 
     let addr: SocketAddr = server_address.parse()?;
 
-    let (_health_reporter, health_service) = tonic_health::server::health_reporter();
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+
+    // The auth service is critical, calls fall back to Postgres while the cache is down. Postgres is
+    // critical unless stale cached copies are served while it is down.
+    let mut probes: Vec<Box<dyn Probe>> = vec![
+        Box::new(PostgresProbe {
+            database_connection: app_state.global_state.read_client.clone(),
+            critical: app_state.cache_policy.stale_ttl.is_zero(),
+        }),
+        Box::new(CacheProbe { cache: app_state.cache.clone() }),
+    ];
+
+    if app_state.auth_chain.contains(&AuthMethod::Bearer) {
+        probes.push(Box::new(AuthProbe { auth_url: app_state.auth_url.clone() }));
+    }
+
+    // Not ready until the cache is warmed, so a fresh deploy doesn't send its first traffic to Postgres
+    let health_checker = HealthChecker::new(health_reporter, probes, app_state.health_interval, app_state.health_timeout).await;
 
     let database_connection = app_state.global_state.database_client.clone();
     
-    let cache_client = app_state.cache_client.clone();
+    let cache = app_state.cache.clone();
 
-    let asset_details_service = grpc::asset_details::AssetDetailsService {
-        database_connection,
-        cache_client
+    // Company lookups only read, so they go to the read replica when one is configured
+    let asset_details_service = AssetDetailsService {
+        query_timeout: app_state.query_timeout,
+        entitlement_policy: app_state.entitlement_policy.clone(),
+        ..AssetDetailsService::new(app_state.global_state.read_client.clone(), cache, app_state.cache_policy.clone(), app_state.cache_format)
     };
 
-    let asset_details_server = AssetDetailsServer::new(asset_details_service);
+    // Warming is best effort, once it is done whether or not it succeeded the replica reports
+    // SERVING for as long as its dependencies are up
+    let warming_state = app_state.clone();
+    let health_shutdown = shutdown.clone();
+
+    tokio::spawn(async move {
+        if let Some(cache_warming) = &warming_state.cache_warming {
+            let warm_result = cache_warming.warm(
+                &warming_state.global_state.read_client,
+                warming_state.cache.as_ref(),
+                &warming_state.cache_policy,
+                warming_state.cache_format,
+            ).await;
+
+            if let Err(e) = warm_result {
+                tracing::error!("Failed to warm the cache: {}", e);
+            }
+        }
+
+        health_checker.run(health_shutdown).await;
+    });
+
+    // Every AssetDetails and ApiAudit call is recorded to the audit log, rejected ones included.
+    // Failures to record never affect the call.
+    let audit_logger = AuditLogger::start(database_connection.clone(), &app_state.audit);
+
+    let asset_details_server = AssetDetailsServer::new(AuditedAssetDetails {
+        inner: asset_details_service,
+    });
+
+    let api_audit_server = ApiAuditServer::new(AuditedApiAudit {
+        inner: grpc::api_audit::ApiAuditService {
+            database_connection: app_state.global_state.database_client.clone(),
+        },
+    });
 
     // Create an instance of the auth interceptor, essentially a gRPC middleware for ensuring
-    // that requests are authenticated
-    let auth_url = app_state.auth_url;
-    let auth_service = AuthServiceImpl::new(auth_url.clone());
+    // that requests are authenticated by one of the configured authenticators
+    tracing::info!("Authenticator chain: {:?}", app_state.auth_chain);
     let auth_interceptor = AuthInterceptor {
-        auth_service: Arc::new(auth_service),
+        authenticators: Arc::new(build_authenticators(&app_state)),
     };
 
-    // QoS for the server, including load shedding, timeouts, and concurrency limits
+    // QoS for the server, including load shedding. Request IDs, metrics and auditing sit outside it
+    // so shed calls are identified, counted and audited too. The request timeout is enforced by
+    // tonic outside every layer, so timed out and cancelled calls are counted and audited when
+    // tonic drops them.
     let layered_server = ServiceBuilder::new()
+        .layer(RequestIdLayer)
+        .layer(RequestMetricsLayer { request_timeout: REQUEST_TIMEOUT })
+        .layer(AuditLayer { audit_logger: audit_logger.clone(), request_timeout: REQUEST_TIMEOUT })
         .load_shed()
-        .timeout(Duration::from_secs(10)) // Increase timeout
         .into_inner();
 
-    Server::builder()
+    // TLS is optional, in-cluster traffic is usually covered by the service mesh. With hot-reload
+    // enabled we terminate TLS ourselves so certificates can be swapped without a restart,
+    // otherwise tonic's static TLS config is used.
+    let mut server_builder = Server::builder();
+    let mut reloading_acceptor: Option<ReloadingTlsAcceptor> = None;
+
+    match &app_state.tls {
+        Some(tls_settings) if tls_settings.reload_interval.is_zero() => {
+            tracing::info!("Serving TLS with certificate {}", tls_settings.cert_path);
+            server_builder = server_builder.tls_config(tls::server_tls_config(tls_settings)?)?;
+        }
+        Some(tls_settings) => {
+            tracing::info!("Serving TLS with certificate {}, reloading every {:?}", tls_settings.cert_path, tls_settings.reload_interval);
+            let acceptor = ReloadingTlsAcceptor::new(tls_settings.clone())?;
+            acceptor.spawn_reloader();
+            reloading_acceptor = Some(acceptor);
+        }
+        None => {
+            tracing::info!("Serving plaintext, TLS is not configured");
+        }
+    }
+
+    // Reflection is served next to health, or behind the authenticator chain, or not at all
+    tracing::info!("Reflection: {:?}", app_state.reflection_mode);
+    let (reflection_v1, reflection_v1alpha) = (reflection_service_v1()?, reflection_service_v1alpha()?);
+    let public_reflection = app_state.reflection_mode == ReflectionMode::Public;
+    let authenticated_reflection = app_state.reflection_mode == ReflectionMode::Authenticated;
+
+    let router = server_builder
         .concurrency_limit_per_connection(128) // Increase concurrency limit
-        .timeout(Duration::from_secs(5)) // Increase timeout
+        .timeout(REQUEST_TIMEOUT)
         .max_connection_age(Duration::from_secs(30)) // Increase max connection age
         .tcp_keepalive(Some(Duration::from_secs(15))) // Enable TCP keepalive
         .http2_keepalive_interval(Some(Duration::from_secs(30))) // Enable HTTP/2 keepalive
         .http2_keepalive_timeout(Some(Duration::from_secs(10))) // Set HTTP/2 keepalive timeout
+        .trace_fn(request_span)
         .layer(layered_server)
         .add_service(health_service)
-        .add_service(InterceptorFor::new(asset_details_server, auth_interceptor))
-        .serve(addr)
-        .await?;
+        .add_optional_service(public_reflection.then(|| reflection_v1.clone()))
+        .add_optional_service(public_reflection.then(|| reflection_v1alpha.clone()))
+        .add_optional_service(authenticated_reflection.then(|| InterceptorFor::new(reflection_v1, auth_interceptor.clone())))
+        .add_optional_service(authenticated_reflection.then(|| InterceptorFor::new(reflection_v1alpha, auth_interceptor.clone())))
+        .add_service(InterceptorFor::new(asset_details_server, auth_interceptor.clone()))
+        .add_service(InterceptorFor::new(api_audit_server, auth_interceptor));
+
+    // On shutdown the health checker reports NOT_SERVING, the server keeps serving for the delay
+    // so load balancers and clients watching health move new calls elsewhere, then stops accepting
+    // connections
+    let shutdown_delay = app_state.shutdown_delay;
+    let stop_accepting = {
+        let shutdown = shutdown.clone();
+
+        async move {
+            shutdown.wait().await;
+            tracing::info!("Closing the listener in {:?}", shutdown_delay);
+
+            tokio::time::sleep(shutdown_delay).await;
+            tracing::info!("Stopped accepting calls, draining in-flight calls");
+        }
+    };
+
+    let serving = async {
+        match reloading_acceptor {
+            Some(acceptor) => {
+                let listener = tokio::net::TcpListener::bind(addr).await?;
+                router.serve_with_incoming_shutdown(acceptor.incoming(listener), stop_accepting).await?;
+            }
+            None => {
+                router.serve_with_shutdown(addr, stop_accepting).await?;
+            }
+        }
+
+        Ok::<(), Box<dyn std::error::Error>>(())
+    };
+
+    // Calls still running after the drain timeout are cut rather than holding up the rollout
+    let drain_timeout = app_state.drain_timeout;
+    let drain_deadline = async {
+        shutdown.wait().await;
+        tokio::time::sleep(shutdown_delay + drain_timeout).await;
+    };
+
+    tokio::select! {
+        served = serving => {
+            served?;
+            tracing::info!("Drained in-flight calls");
+        }
+        _ = drain_deadline => {
+            tracing::warn!("In-flight calls did not finish within {:?}, cutting them", drain_timeout);
+        }
+    }
+
+    audit_logger.close(AUDIT_CLOSE_TIMEOUT).await;
 
     Ok(())
 }
@@ -67,13 +293,19 @@ async fn start_server() -> Result<(), Box<dyn std::error::Error>> {
 async fn main() {
     let start = start_server().await;
 
-    match start {
+    match &start {
         Ok(_) => {
-            tracing::info!("Server started successfully");
+            tracing::info!("Server stopped");
         },
         Err(e) => {
             tracing::error!("Error starting server: {:?}", e);
-            std::process::exit(1);
         }
     }
+
+    // Buffered spans and logs are lost if the process exits first
+    shutdown_observability();
+
+    if start.is_err() {
+        std::process::exit(1);
+    }
 }
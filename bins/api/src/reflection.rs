@@ -0,0 +1,117 @@
+use grpc::api_audit::api_audit::api_audit_server::SERVICE_NAME as API_AUDIT_SERVICE;
+use grpc::asset_details::asset_details::asset_details_server::SERVICE_NAME as ASSET_DETAILS_SERVICE;
+use tonic_health::pb::health_server::SERVICE_NAME as HEALTH_SERVICE;
+use tonic_reflection::server::{v1, v1alpha, Builder};
+use utils::error::{Error, ErrorType};
+
+/// The services listed by reflection, only the ones this binary serves although every proto in
+/// `protobufs/` can be looked up
+const REFLECTED_SERVICES: [&str; 3] = [ASSET_DETAILS_SERVICE, API_AUDIT_SERVICE, HEALTH_SERVICE];
+
+/// Whether the reflection service is served, and whether callers have to authenticate to use it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReflectionMode {
+    /// Not served, so the API's schema isn't handed to anyone who asks
+    #[default]
+    Disabled,
+    /// Served behind the authenticator chain, like AssetDetails
+    Authenticated,
+    /// Served to any caller, like the health service
+    Public,
+}
+
+impl TryFrom<&str> for ReflectionMode {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "disabled" => Ok(ReflectionMode::Disabled),
+            "authenticated" => Ok(ReflectionMode::Authenticated),
+            "public" => Ok(ReflectionMode::Public),
+            _ => Err(Error::new(ErrorType::InvalidConfig, format!("Unknown reflection mode: {}", value))),
+        }
+    }
+}
+
+fn builder() -> Builder<'static> {
+    REFLECTED_SERVICES.iter().fold(
+        Builder::configure().register_encoded_file_descriptor_set(grpc::FILE_DESCRIPTOR_SET),
+        |builder, service| builder.with_service_name(*service),
+    )
+}
+
+fn reflection_error(e: tonic_reflection::server::Error) -> Error {
+    Error::new(ErrorType::ParseError, format!("Unable to build the reflection service: {}", e))
+}
+
+/// The `grpc.reflection.v1` reflection service, used by current grpcurl releases
+///
+/// # Errors
+///
+/// * If the descriptor set cannot be decoded, returns a ParseError
+pub fn reflection_service_v1() -> Result<v1::ServerReflectionServer<impl v1::ServerReflection>, Error> {
+    builder().build_v1().map_err(reflection_error)
+}
+
+/// The `grpc.reflection.v1alpha` reflection service, still the only one some clients such as
+/// Postman and older grpcurl releases ask for
+///
+/// # Errors
+///
+/// * If the descriptor set cannot be decoded, returns a ParseError
+pub fn reflection_service_v1alpha() -> Result<v1alpha::ServerReflectionServer<impl v1alpha::ServerReflection>, Error> {
+    builder().build_v1alpha().map_err(reflection_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::{Channel, Server};
+    use tonic_reflection::pb::v1::server_reflection_client::ServerReflectionClient;
+    use tonic_reflection::pb::v1::server_reflection_request::MessageRequest;
+    use tonic_reflection::pb::v1::server_reflection_response::MessageResponse;
+    use tonic_reflection::pb::v1::ServerReflectionRequest;
+
+    async fn reflect(client: &mut ServerReflectionClient<Channel>, message_request: MessageRequest) -> MessageResponse {
+        let request = ServerReflectionRequest { host: String::new(), message_request: Some(message_request) };
+
+        let mut responses = client.server_reflection_info(futures::stream::iter(vec![request])).await.unwrap().into_inner();
+
+        responses.message().await.unwrap().unwrap().message_response.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_lists_served_services_and_resolves_their_files() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(Server::builder().add_service(reflection_service_v1().unwrap()).serve_with_incoming(incoming));
+
+        let channel = Channel::from_shared(format!("http://{}", address)).unwrap().connect().await.unwrap();
+        let mut client = ServerReflectionClient::new(channel);
+
+        let MessageResponse::ListServicesResponse(listed) = reflect(&mut client, MessageRequest::ListServices(String::new())).await else {
+            panic!("expected a list of services");
+        };
+        let mut services: Vec<String> = listed.service.into_iter().map(|service| service.name).collect();
+        services.sort();
+        assert_eq!(services, vec![
+            API_AUDIT_SERVICE.to_string(),
+            ASSET_DETAILS_SERVICE.to_string(),
+            HEALTH_SERVICE.to_string(),
+        ]);
+
+        let MessageResponse::FileDescriptorResponse(files) = reflect(&mut client, MessageRequest::FileContainingSymbol(ASSET_DETAILS_SERVICE.to_string())).await else {
+            panic!("expected the file defining AssetDetails");
+        };
+        assert!(!files.file_descriptor_proto.is_empty());
+    }
+
+    #[test]
+    fn test_mode_from_config() {
+        assert_eq!(ReflectionMode::try_from("Public").unwrap(), ReflectionMode::Public);
+        assert_eq!(ReflectionMode::try_from("authenticated").unwrap(), ReflectionMode::Authenticated);
+        assert!(ReflectionMode::try_from("open").is_err());
+    }
+}
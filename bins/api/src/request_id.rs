@@ -0,0 +1,110 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use grpc::request_id::{request_id_from_headers, REQUEST_ID_HEADER};
+use tonic::codegen::http::{HeaderValue, Request, Response};
+use tower::{Layer, Service};
+
+/// Gives every call an `x-request-id`, the caller's or a generated one. The ID is set on the
+/// request for the auth interceptor and handlers, recorded on the `grpc.request` span so every log
+/// line of the call carries it, and returned in the response metadata.
+#[derive(Debug, Clone, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestId { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestId<S> {
+    inner: S,
+}
+
+impl<S, RequestBody, ResponseBody> Service<Request<RequestBody>> for RequestId<S>
+where
+    S: Service<Request<RequestBody>, Response = Response<ResponseBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    RequestBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<RequestBody>) -> Self::Future {
+        let request_id: String = request_id_from_headers(request.headers());
+
+        // Always valid, request IDs are checked to be visible ASCII
+        let header_value: Option<HeaderValue> = HeaderValue::from_str(&request_id).ok();
+
+        if let Some(header_value) = &header_value {
+            request.headers_mut().insert(REQUEST_ID_HEADER, header_value.clone());
+        }
+
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            // Polled inside the `grpc.request` span, before the interceptor or handler log anything
+            tracing::Span::current().record("request_id", request_id.as_str());
+
+            let mut response = response.await?;
+
+            if let Some(header_value) = header_value {
+                response.headers_mut().insert(REQUEST_ID_HEADER, header_value);
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::{Channel, Server};
+    use tonic_health::pb::health_client::HealthClient;
+    use tonic_health::pb::HealthCheckRequest;
+
+    fn request(request_id: Option<&str>) -> tonic::Request<HealthCheckRequest> {
+        let mut request = tonic::Request::new(HealthCheckRequest { service: String::new() });
+
+        if let Some(request_id) = request_id {
+            request.metadata_mut().insert(REQUEST_ID_HEADER, request_id.parse().unwrap());
+        }
+
+        request
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_returned() {
+        let (_reporter, health_service) = tonic_health::server::health_reporter();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(Server::builder()
+            .layer(RequestIdLayer)
+            .add_service(health_service)
+            .serve_with_incoming(incoming));
+
+        let channel = Channel::from_shared(format!("http://{}", address)).unwrap().connect().await.unwrap();
+        let mut client = HealthClient::new(channel);
+
+        let response = client.check(request(Some("checkout-7f3a"))).await.unwrap();
+        assert_eq!(response.metadata().get(REQUEST_ID_HEADER).unwrap(), "checkout-7f3a");
+
+        let first = client.check(request(None)).await.unwrap();
+        let second = client.check(request(None)).await.unwrap();
+        let generated = first.metadata().get(REQUEST_ID_HEADER).unwrap();
+        assert!(uuid::Uuid::parse_str(generated.to_str().unwrap()).is_ok());
+        assert_ne!(generated, second.metadata().get(REQUEST_ID_HEADER).unwrap());
+    }
+}
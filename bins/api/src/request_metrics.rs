@@ -0,0 +1,206 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::Code;
+use tower::{BoxError, Layer, Service};
+use tower::load_shed::error::Overloaded;
+
+/// tonic starts timing a call before the layers are called, so a call dropped this close to its
+/// deadline is taken to have exceeded it
+const DEADLINE_TOLERANCE: Duration = Duration::from_millis(5);
+
+/// Records the count and latency of every gRPC call by method and status code, including calls
+/// that time out or are cancelled by the client
+#[derive(Debug, Clone)]
+pub struct RequestMetricsLayer {
+    /// The server's request timeout, to tell timed out calls from cancelled ones
+    pub request_timeout: Duration,
+}
+
+impl<S> Layer<S> for RequestMetricsLayer {
+    type Service = RequestMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestMetrics { inner, request_timeout: self.request_timeout }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestMetrics<S> {
+    inner: S,
+    request_timeout: Duration,
+}
+
+/// The client's `grpc-timeout`, e.g. `100m` for 100 milliseconds
+fn grpc_timeout(headers: &HeaderMap) -> Option<Duration> {
+    let raw = headers.get("grpc-timeout")?.to_str().ok()?;
+    let (value, unit) = raw.split_at(raw.len().checked_sub(1)?);
+    let value: u64 = value.parse().ok().filter(|_| value.len() <= 8)?;
+
+    match unit {
+        "H" => Some(Duration::from_secs(value * 60 * 60)),
+        "M" => Some(Duration::from_secs(value * 60)),
+        "S" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_millis(value)),
+        "u" => Some(Duration::from_micros(value)),
+        "n" => Some(Duration::from_nanos(value)),
+        _ => None,
+    }
+}
+
+/// Reports how a call ended once it ends. tonic enforces timeouts outside of every layer and
+/// drops the call's future when one fires, as it does when the client goes away, so a guard
+/// dropped before [`CallGuard::finish`] reports the call as `DeadlineExceeded` or `Cancelled`.
+pub struct CallGuard<F: FnOnce(Code, Duration)> {
+    report: Option<F>,
+    started_at: Instant,
+    deadline: Duration,
+}
+
+impl<F: FnOnce(Code, Duration)> CallGuard<F> {
+    /// Start timing a call
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - The call's request headers, holding the client's `grpc-timeout` if it set one
+    /// * `request_timeout` - The server's request timeout
+    /// * `report` - Called once with the call's status code and latency
+    pub fn new(headers: &HeaderMap, request_timeout: Duration, report: F) -> Self {
+        let deadline = grpc_timeout(headers).map_or(request_timeout, |timeout| timeout.min(request_timeout));
+
+        CallGuard { report: Some(report), started_at: Instant::now(), deadline }
+    }
+
+    /// Report a call the inner service answered
+    pub fn finish(mut self, code: Code) {
+        if let Some(report) = self.report.take() {
+            report(code, self.started_at.elapsed());
+        }
+    }
+}
+
+impl<F: FnOnce(Code, Duration)> Drop for CallGuard<F> {
+    fn drop(&mut self) {
+        if let Some(report) = self.report.take() {
+            let latency = self.started_at.elapsed();
+
+            let code = match latency + DEADLINE_TOLERANCE >= self.deadline {
+                true => Code::DeadlineExceeded,
+                false => Code::Cancelled,
+            };
+
+            report(code, latency);
+        }
+    }
+}
+
+/// The gRPC status code of a response. Errors, including rejected credentials, are sent as
+/// trailers-only responses with the status in the headers, while successful unary responses only
+/// send it in the trailers.
+pub fn status_code(headers: &HeaderMap) -> tonic::Code {
+    headers.get("grpc-status")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i32>().ok())
+        .map(tonic::Code::from_i32)
+        .unwrap_or(tonic::Code::Ok)
+}
+
+/// The gRPC status code tonic answers a middleware error with
+pub fn error_code(error: &BoxError) -> tonic::Code {
+    match error {
+        _ if error.is::<Overloaded>() => tonic::Code::Unavailable,
+        _ => tonic::Code::Unknown,
+    }
+}
+
+impl<S, RequestBody, ResponseBody> Service<Request<RequestBody>> for RequestMetrics<S>
+where
+    S: Service<Request<RequestBody>, Response = Response<ResponseBody>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    RequestBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<RequestBody>) -> Self::Future {
+        let method: String = request.uri().path().to_string();
+
+        let guard = CallGuard::new(request.headers(), self.request_timeout, move |code: Code, latency: Duration| {
+            let code: String = format!("{:?}", code);
+
+            metrics::counter!("grpc_server_requests_total", "method" => method.clone(), "code" => code.clone()).increment(1);
+            metrics::histogram!("grpc_server_request_duration_seconds", "method" => method, "code" => code).record(latency);
+        });
+
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await.map_err(Into::into);
+
+            guard.finish(match &response {
+                Ok(response) => status_code(response.headers()),
+                Err(error) => error_code(error),
+            });
+
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::codegen::http::HeaderValue;
+
+    #[test]
+    fn test_status_code() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(status_code(&headers), tonic::Code::Ok);
+
+        headers.insert("grpc-status", HeaderValue::from_static("16"));
+        assert_eq!(status_code(&headers), tonic::Code::Unauthenticated);
+    }
+
+    fn reported_code(headers: &HeaderMap, finish: Option<Code>) -> Code {
+        let reported = std::cell::Cell::new(None);
+        let guard = CallGuard::new(headers, Duration::from_secs(5), |code, _| reported.set(Some(code)));
+
+        match finish {
+            Some(code) => guard.finish(code),
+            None => drop(guard),
+        }
+
+        reported.get().unwrap()
+    }
+
+    #[test]
+    fn test_call_guard() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(reported_code(&headers, Some(Code::NotFound)), Code::NotFound);
+        assert_eq!(reported_code(&headers, None), Code::Cancelled);
+
+        // Dropped once the client's deadline passed
+        headers.insert("grpc-timeout", HeaderValue::from_static("0m"));
+        assert_eq!(reported_code(&headers, None), Code::DeadlineExceeded);
+    }
+
+    #[test]
+    fn test_grpc_timeout() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(grpc_timeout(&headers), None);
+
+        headers.insert("grpc-timeout", HeaderValue::from_static("250m"));
+        assert_eq!(grpc_timeout(&headers), Some(Duration::from_millis(250)));
+
+        headers.insert("grpc-timeout", HeaderValue::from_static("3X"));
+        assert_eq!(grpc_timeout(&headers), None);
+    }
+}
@@ -0,0 +1,373 @@
+use std::fs;
+use std::io::Cursor;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use futures::{SinkExt, Stream, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use utils::error::{Error, ErrorType};
+
+/// How long a client has to complete the TLS handshake before the connection is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait after a failed accept, errors such as running out of file descriptors
+/// persist for a while and retrying straight away would spin the accept loop
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// TLS settings for the gRPC server, loaded from config
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    pub cert_path: String,
+    pub key_path: String,
+    /// CA bundle used to verify client certificates, enables TLS client auth when set
+    pub client_ca_path: Option<String>,
+    /// Accept clients that do not present a certificate even when client auth is enabled
+    pub client_auth_optional: bool,
+    /// How often to check the certificate files for changes, zero disables hot-reload
+    pub reload_interval: Duration,
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|e| {
+        Error::new(ErrorType::InvalidConfig, format!("Failed to read {}: {}", path, e))
+    })
+}
+
+/// Build a static tonic TLS config from the certificate files
+///
+/// # Arguments
+///
+/// * `settings` - The TLS settings
+///
+/// # Returns
+///
+/// A ServerTlsConfig to pass to the tonic server builder
+///
+/// # Errors
+///
+/// * If any of the files cannot be read, returns an InvalidConfig error
+pub fn server_tls_config(settings: &TlsSettings) -> Result<ServerTlsConfig, Error> {
+    let identity = Identity::from_pem(read_file(&settings.cert_path)?, read_file(&settings.key_path)?);
+
+    let mut tls_config = ServerTlsConfig::new().identity(identity);
+
+    if let Some(client_ca_path) = &settings.client_ca_path {
+        tls_config = tls_config
+            .client_ca_root(Certificate::from_pem(read_file(client_ca_path)?))
+            .client_auth_optional(settings.client_auth_optional);
+    }
+
+    Ok(tls_config)
+}
+
+/// Build a rustls server config from the certificate files, mirroring what tonic builds from a
+/// ServerTlsConfig so that both paths behave the same
+fn build_rustls_config(settings: &TlsSettings) -> Result<Arc<ServerConfig>, Error> {
+    let invalid = |message: String| Error::new(ErrorType::InvalidConfig, message);
+
+    let provider = Arc::new(ring::default_provider());
+
+    let certs: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut Cursor::new(read_file(&settings.cert_path)?))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(format!("Failed to parse TLS certificate: {}", e)))?;
+
+    let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut Cursor::new(read_file(&settings.key_path)?))
+        .map_err(|e| invalid(format!("Failed to parse TLS private key: {}", e)))?
+        .ok_or_else(|| invalid(format!("No private key found in {}", settings.key_path)))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| invalid(format!("Failed to configure TLS: {}", e)))?;
+
+    let builder = match &settings.client_ca_path {
+        None => builder.with_no_client_auth(),
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+
+            for cert in rustls_pemfile::certs(&mut Cursor::new(read_file(client_ca_path)?)) {
+                let cert = cert.map_err(|e| invalid(format!("Failed to parse client CA: {}", e)))?;
+                roots.add(cert).map_err(|e| invalid(format!("Failed to add client CA: {}", e)))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider);
+            let verifier = if settings.client_auth_optional {
+                verifier.allow_unauthenticated()
+            } else {
+                verifier
+            }
+                .build()
+                .map_err(|e| invalid(format!("Failed to build client verifier: {}", e)))?;
+
+            builder.with_client_cert_verifier(verifier)
+        }
+    };
+
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| invalid(format!("Invalid TLS certificate or key: {}", e)))?;
+
+    config.alpn_protocols.push(b"h2".to_vec());
+
+    Ok(Arc::new(config))
+}
+
+/// Modification times of the certificate files, used to detect changes on disk
+fn file_fingerprint(settings: &TlsSettings) -> Vec<Option<SystemTime>> {
+    [Some(&settings.cert_path), Some(&settings.key_path), settings.client_ca_path.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+        .collect()
+}
+
+/// A TLS acceptor whose certificates are reloaded when the files change on disk. New connections
+/// use the latest certificates, existing connections keep the ones they were established with.
+#[derive(Clone)]
+pub struct ReloadingTlsAcceptor {
+    settings: Arc<TlsSettings>,
+    config: Arc<RwLock<Arc<ServerConfig>>>,
+}
+
+impl ReloadingTlsAcceptor {
+    pub fn new(settings: TlsSettings) -> Result<Self, Error> {
+        let config = build_rustls_config(&settings)?;
+
+        Ok(Self {
+            settings: Arc::new(settings),
+            config: Arc::new(RwLock::new(config)),
+        })
+    }
+
+    fn current_config(&self) -> Arc<ServerConfig> {
+        match self.config.read() {
+            Ok(config) => config.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Spawn a background task that polls the certificate files and swaps in a new config when
+    /// they change. A config that fails to load is logged and the previous one is kept.
+    pub fn spawn_reloader(&self) -> tokio::task::JoinHandle<()> {
+        let acceptor = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(acceptor.settings.reload_interval);
+            let mut last_fingerprint = file_fingerprint(&acceptor.settings);
+
+            loop {
+                interval.tick().await;
+
+                let fingerprint = file_fingerprint(&acceptor.settings);
+                if fingerprint == last_fingerprint {
+                    continue;
+                }
+
+                match build_rustls_config(&acceptor.settings) {
+                    Ok(new_config) => {
+                        match acceptor.config.write() {
+                            Ok(mut config) => *config = new_config,
+                            Err(poisoned) => *poisoned.into_inner() = new_config,
+                        }
+
+                        last_fingerprint = fingerprint;
+                        tracing::info!("Reloaded TLS certificates from {}", acceptor.settings.cert_path);
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to reload TLS certificates, keeping previous: {}", e);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Turn a TCP listener into a stream of TLS connections for `serve_with_incoming`. Handshakes
+    /// run on their own tasks so a slow client cannot hold up the accept loop.
+    pub fn incoming(self, listener: TcpListener) -> impl Stream<Item = Result<TlsStream<TcpStream>, std::io::Error>> {
+        let (sender, receiver) = futures::channel::mpsc::channel::<TlsStream<TcpStream>>(128);
+
+        tokio::spawn(async move {
+            while !sender.is_closed() {
+                let (stream, peer_address) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        tracing::error!("Failed to accept connection: {}", e);
+                        tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                };
+
+                if let Err(e) = stream.set_nodelay(true) {
+                    tracing::debug!("Failed to set TCP_NODELAY for {}: {}", peer_address, e);
+                }
+
+                let tls_acceptor = TlsAcceptor::from(self.current_config());
+                let mut sender = sender.clone();
+
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, tls_acceptor.accept(stream)).await {
+                        Ok(Ok(tls_stream)) => {
+                            let _ = sender.send(tls_stream).await;
+                        }
+                        Ok(Err(e)) => {
+                            tracing::debug!("TLS handshake failed for {}: {}", peer_address, e);
+                        }
+                        Err(_) => {
+                            tracing::debug!("TLS handshake timed out for {}", peer_address);
+                        }
+                    }
+                });
+            }
+        });
+
+        receiver.map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::TlsConnector;
+
+    /// Server and client certificates issued by a throwaway test CA whose key was discarded
+    const TESTDATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls");
+
+    fn testdata(name: &str) -> String {
+        format!("{}/{}", TESTDATA, name)
+    }
+
+    fn settings(cert_path: String, key_path: String, client_ca_path: Option<String>) -> TlsSettings {
+        TlsSettings {
+            cert_path,
+            key_path,
+            client_ca_path,
+            client_auth_optional: false,
+            reload_interval: Duration::from_millis(10),
+        }
+    }
+
+    fn client_config(client_identity: Option<&str>) -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut Cursor::new(read_file(&testdata("ca.pem")).unwrap())) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+
+        let config = match client_identity {
+            None => builder.with_no_client_auth(),
+            Some(name) => {
+                let certs = rustls_pemfile::certs(&mut Cursor::new(read_file(&testdata(&format!("{}.pem", name))).unwrap()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap();
+                let key = rustls_pemfile::private_key(&mut Cursor::new(read_file(&testdata(&format!("{}.key", name))).unwrap()))
+                    .unwrap()
+                    .unwrap();
+
+                builder.with_client_auth_cert(certs, key).unwrap()
+            }
+        };
+
+        Arc::new(config)
+    }
+
+    /// Run a handshake over an in-memory pipe, returning whether the server accepted it
+    async fn handshake(server_config: Arc<ServerConfig>, client_identity: Option<&str>) -> bool {
+        let (client_stream, server_stream) = tokio::io::duplex(16 * 1024);
+        let connector = TlsConnector::from(client_config(client_identity));
+        let server_name = ServerName::try_from("localhost").unwrap();
+
+        let (accepted, _) = tokio::join!(
+            TlsAcceptor::from(server_config).accept(server_stream),
+            connector.connect(server_name, client_stream),
+        );
+
+        accepted.is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_without_client_ca() {
+        let config = build_rustls_config(&settings(testdata("server.pem"), testdata("server.key"), None)).unwrap();
+
+        assert_eq!(config.alpn_protocols, vec![b"h2".to_vec()]);
+        assert!(handshake(config, None).await);
+    }
+
+    #[tokio::test]
+    async fn test_with_client_ca() {
+        let mut tls_settings = settings(testdata("server.pem"), testdata("server.key"), Some(testdata("ca.pem")));
+        let config = build_rustls_config(&tls_settings).unwrap();
+
+        assert!(handshake(config.clone(), Some("client")).await);
+        assert!(!handshake(config, None).await);
+
+        tls_settings.client_auth_optional = true;
+        let config = build_rustls_config(&tls_settings).unwrap();
+
+        assert!(handshake(config, None).await);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        // A certificate where the key should be
+        assert!(build_rustls_config(&settings(testdata("server.pem"), testdata("server.pem"), None)).is_err());
+        assert!(build_rustls_config(&settings(testdata("server.pem"), testdata("missing.key"), None)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reloads_when_files_change() {
+        let directory = std::env::temp_dir().join(format!("api-tls-{}", uuid::Uuid::now_v7()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let cert_path = directory.join("server.pem");
+        let key_path = directory.join("server.key");
+        fs::copy(testdata("server.pem"), &cert_path).unwrap();
+        fs::copy(testdata("server.key"), &key_path).unwrap();
+
+        let acceptor = ReloadingTlsAcceptor::new(settings(
+            cert_path.to_string_lossy().to_string(),
+            key_path.to_string_lossy().to_string(),
+            None,
+        )).unwrap();
+        let reloader = acceptor.spawn_reloader();
+
+        let initial = acceptor.current_config();
+
+        // Let the reloader take its first fingerprint before the files change
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // An unreadable certificate is skipped and the previous config kept
+        fs::write(&cert_path, b"not a certificate").unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(Arc::ptr_eq(&initial, &acceptor.current_config()));
+
+        fs::copy(testdata("server-renewed.pem"), &cert_path).unwrap();
+        fs::copy(testdata("server-renewed.key"), &key_path).unwrap();
+
+        let mut reloaded = false;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            if !Arc::ptr_eq(&initial, &acceptor.current_config()) {
+                reloaded = true;
+                break;
+            }
+        }
+
+        reloader.abort();
+        let _ = fs::remove_dir_all(&directory);
+
+        assert!(reloaded, "the renewed certificate was not loaded");
+        assert!(handshake(acceptor.current_config(), None).await);
+    }
+}
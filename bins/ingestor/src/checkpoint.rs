@@ -0,0 +1,74 @@
+use chrono::Utc;
+use sea_orm::{sea_query, ActiveValue, DatabaseConnection, EntityTrait};
+use entities::ingestor_checkpoint;
+use utils::error::{Error, ErrorType};
+
+/// The job the checkpoint is stored under
+const CHECKPOINT_JOB: &str = "asset_details_ingestor";
+
+fn checkpoint_error(context: &str, e: sea_orm::DbErr) -> Error {
+    Error::new(ErrorType::DatabaseError, format!("{}: {}", context, e))
+}
+
+/// Where an interrupted run stopped, tickers are processed in order so the next run resumes after
+/// the last one
+///
+/// # Errors
+///
+/// * If the checkpoint cannot be read, returns a DatabaseError
+pub async fn load_checkpoint(database_connection: &DatabaseConnection) -> Result<Option<ingestor_checkpoint::Model>, Error> {
+    ingestor_checkpoint::Entity::find_by_id(CHECKPOINT_JOB)
+        .one(database_connection)
+        .await
+        .map_err(|e| checkpoint_error("Failed to read the ingestor checkpoint", e))
+}
+
+/// Remember the last fully processed ticker of an interrupted run
+///
+/// # Arguments
+///
+/// * `database_connection` - The primary database
+/// * `last_ticker` - The last ticker that was processed
+/// * `processed` - How many tickers the run has processed, including resumed runs before it
+///
+/// # Errors
+///
+/// * If the checkpoint cannot be written, returns a DatabaseError
+pub async fn save_checkpoint(database_connection: &DatabaseConnection, last_ticker: &str, processed: usize) -> Result<(), Error> {
+    let checkpoint = ingestor_checkpoint::ActiveModel {
+        job: ActiveValue::Set(CHECKPOINT_JOB.to_string()),
+        last_ticker: ActiveValue::Set(last_ticker.to_string()),
+        processed: ActiveValue::Set(processed as i32),
+        updated_at: ActiveValue::Set(Utc::now().into()),
+    };
+
+    let conflict_statement = sea_query::OnConflict::column(ingestor_checkpoint::Column::Job)
+        .update_columns(vec![
+            ingestor_checkpoint::Column::LastTicker,
+            ingestor_checkpoint::Column::Processed,
+            ingestor_checkpoint::Column::UpdatedAt,
+        ])
+        .to_owned();
+
+    ingestor_checkpoint::Entity::insert(checkpoint)
+        .on_conflict(conflict_statement)
+        .exec(database_connection)
+        .await
+        .map_err(|e| checkpoint_error("Failed to write the ingestor checkpoint", e))?;
+
+    Ok(())
+}
+
+/// Forget the checkpoint once a run gets through every ticker, so the next run starts over
+///
+/// # Errors
+///
+/// * If the checkpoint cannot be deleted, returns a DatabaseError
+pub async fn clear_checkpoint(database_connection: &DatabaseConnection) -> Result<(), Error> {
+    ingestor_checkpoint::Entity::delete_by_id(CHECKPOINT_JOB)
+        .exec(database_connection)
+        .await
+        .map_err(|e| checkpoint_error("Failed to clear the ingestor checkpoint", e))?;
+
+    Ok(())
+}
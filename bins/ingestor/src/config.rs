@@ -1,6 +1,9 @@
-use config::GlobalState;
-use utils::env::{get_optional_env_var, get_required_env_var};
-use utils::error::{Error};
+use config::{check, CacheConfig, GlobalConfig, GlobalState, Validate};
+use grpc::asset_details::CacheFormat;
+use grpc::asset_details::warming::CacheWarming;
+use serde::{Deserialize, Serialize};
+use utils::cache::{CachePolicy, RedisCache};
+use utils::error::Error;
 
 #[derive(Debug, Clone)]
 pub struct IngestorState {
@@ -9,28 +12,106 @@ pub struct IngestorState {
     pub cloudflare_api_key: String,
     pub cloudflare_account_id: String,
     pub cloudflare_account_hash: Option<String>,
+    /// The API's cache, used to invalidate entries of ingested companies, None if not configured
+    pub cache: Option<RedisCache>,
+    /// Number of upserted companies whose cache entries are invalidated together
+    pub invalidation_batch_size: usize,
+    /// The API's cache format, in the response format ingested companies are written to the cache
+    /// instead of only being invalidated
+    pub cache_format: CacheFormat,
+    /// The TTLs and jitter of entries written to the cache, matching the API's
+    pub cache_policy: CachePolicy,
+    /// Companies preloaded into the cache at the end of each run, None if not configured
+    pub cache_warming: Option<CacheWarming>,
 }
 
-pub async fn load_state() -> Result<IngestorState, Error> {
-    let global_state: GlobalState = config::load_state().await;
+/// The ingestor's settings, see [`config::load_config`] for where they are read from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IngestorConfig {
+    #[serde(flatten)]
+    pub global: GlobalConfig,
+    pub polygon: PolygonConfig,
+    pub cloudflare: CloudflareConfig,
+    /// Shared with the API, the cache is only used when `cache.url` is set
+    pub cache: CacheConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolygonConfig {
+    pub api_key: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CloudflareConfig {
+    pub api_key: String,
+    pub account_id: String,
+    /// Optional, images are served through Cloudflare's delivery URL when set
+    pub account_hash: String,
+}
+
+impl Validate for IngestorConfig {
+    fn validate(&self, errors: &mut Vec<String>) {
+        self.global.validate(errors);
+        self.cache.validate(errors);
 
-    let polygon_api_key = get_required_env_var("POLYGON_API_KEY");
-    let cloudflare_api_key = get_required_env_var("CLOUDFLARE_API_KEY");
-    let cloudflare_account_id = get_required_env_var("CLOUDFLARE_ACCOUNT_ID");
+        let required = [
+            ("POLYGON_API_KEY", &self.polygon.api_key),
+            ("CLOUDFLARE_API_KEY", &self.cloudflare.api_key),
+            ("CLOUDFLARE_ACCOUNT_ID", &self.cloudflare.account_id),
+        ];
 
-    let try_cloudflare_account_hash = get_optional_env_var("CLOUDFLARE_ACCOUNT_HASH", "".to_string());
-    let cloudflare_account_hash: Option<String> = match try_cloudflare_account_hash.as_str() {
-        "" => None,
-        _ => Some(try_cloudflare_account_hash)
+        for (name, value) in required {
+            if value.is_empty() {
+                errors.push(format!("{} is required", name));
+            }
+        }
+
+        check(errors, CacheFormat::try_from(self.cache.format.as_str()));
+    }
+}
+
+pub async fn load_state(config: IngestorConfig) -> Result<IngestorState, Error> {
+
+    let global_state: GlobalState = config::load_state(&config.global).await?;
+
+    let cache: Option<RedisCache> = match config.cache.redis_target()? {
+        None => {
+            tracing::warn!("CACHE_URL is not set, cache entries will not be invalidated after ingest");
+            None
+        }
+        Some(redis_target) => match RedisCache::connect(&redis_target, &config.cache.connection_settings()).await {
+            Ok(cache) => Some(cache),
+            // Entries written by the API still expire on their own, so a Redis outage only costs
+            // a run when invalidation is required
+            Err(e) if config.cache.invalidation_optional => {
+                tracing::warn!("Redis is unreachable, cache entries will not be invalidated after ingest: {}", e);
+                metrics::gauge!("ingestor_cache_invalidation_skipped").set(1.0);
+                None
+            }
+            Err(e) => return Err(e),
+        },
     };
 
-    // for each strip all single and double quote from start/end if present
+    let cache_warming: Option<CacheWarming> = CacheWarming::from_config(
+        &config.cache.warm.symbols.join(","),
+        config.cache.warm.top_n,
+        config.cache.warm.concurrency,
+    );
+
     let app_state: IngestorState = IngestorState {
         global_state,
-        polygon_api_key,
-        cloudflare_api_key,
-        cloudflare_account_id,
-        cloudflare_account_hash
+        polygon_api_key: config.polygon.api_key,
+        cloudflare_api_key: config.cloudflare.api_key,
+        cloudflare_account_id: config.cloudflare.account_id,
+        cloudflare_account_hash: Some(config.cloudflare.account_hash).filter(|account_hash| !account_hash.is_empty()),
+        cache,
+        invalidation_batch_size: config.cache.invalidation_batch_size,
+        cache_format: CacheFormat::try_from(config.cache.format.as_str())?,
+        cache_policy: config.cache.policy()?,
+        cache_warming
     };
 
     Ok(app_state)
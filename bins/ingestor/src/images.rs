@@ -78,10 +78,12 @@ async fn upload_image_to_cdn(
     match upload_result {
         Ok(response) => {
             tracing::debug!("Called upload image with response: {:?}", response);
+            metrics::counter!("ingestor_cloudflare_requests_total", "outcome" => if response.success { "ok" } else { "rejected" }).increment(1);
             Ok(response)
         },
         Err(e) => {
             tracing::error!("Failed to upload image for {}", e);
+            metrics::counter!("ingestor_cloudflare_requests_total", "outcome" => "error").increment(1);
             Err(Error::new(ErrorType::ThirdPartyError, format!("Failed to upload image for {}", e)))
         }
     }
@@ -106,6 +108,34 @@ async fn upload_image_to_cdn(
 /// 
 /// An error is returned if the image URL cannot be processed
 async fn process_image_url(cloudflare_client: &cloudflare_sdk::Client, image_type: ImageType, url: String, ticker: String, polygon_api_key: String) -> Result<String, Error> {
+    let image_type_label: String = image_type.to_string();
+
+    let processed = upload_image(cloudflare_client, image_type, url, ticker, polygon_api_key).await;
+
+    let result = match &processed {
+        Ok(ImageUpload::Uploaded(_)) => "uploaded",
+        Ok(ImageUpload::Existing(_)) => "existing",
+        Err(e) if matches!(e.error_type, ErrorType::ParseError) => "unsupported",
+        Err(_) => "failed",
+    };
+
+    metrics::counter!("ingestor_image_uploads_total", "image_type" => image_type_label, "result" => result).increment(1);
+
+    processed.map(|upload| match upload {
+        ImageUpload::Uploaded(url) | ImageUpload::Existing(url) => url,
+    })
+}
+
+/// Where a processed image ended up
+enum ImageUpload {
+    /// Uploaded by this run
+    Uploaded(String),
+    /// Already on the CDN from an earlier run
+    Existing(String),
+}
+
+/// Upload an image to the CDN, see [`process_image_url`]
+async fn upload_image(cloudflare_client: &cloudflare_sdk::Client, image_type: ImageType, url: String, ticker: String, polygon_api_key: String) -> Result<ImageUpload, Error> {
     tracing::debug!("Processing image URL: {}", url);
 
     let image_format: Option<String> = parse_image_format(&url);
@@ -138,7 +168,7 @@ async fn process_image_url(cloudflare_client: &cloudflare_sdk::Client, image_typ
             .ok_or_else(|| Error::new(ErrorType::ThirdPartyError, "Failed to upload image".to_string()))?
             .to_owned();
 
-        return Ok(new_url);
+        return Ok(ImageUpload::Uploaded(new_url));
     }
 
     if response.errors.len() > 0 {
@@ -155,7 +185,7 @@ async fn process_image_url(cloudflare_client: &cloudflare_sdk::Client, image_typ
                         
                         tracing::info!("Existing URL: {}", existing_url);
 
-                        return Ok(existing_url);
+                        return Ok(ImageUpload::Existing(existing_url));
                     }
 
                     return Err(Error::new(ErrorType::ThirdPartyError, error.message.clone()));
@@ -1,19 +1,114 @@
+mod checkpoint;
 mod config;
 mod images;
 
-use crate::config::IngestorState;
+use crate::checkpoint::{clear_checkpoint, load_checkpoint, save_checkpoint};
+use crate::config::{IngestorConfig, IngestorState};
+use ::config::{init_observability, load_config, load_migrate_config, redacted, run_migrate_command, shutdown_observability, split_command, MigrateConfig, Shutdown};
+use entities::company::Model;
+use grpc::asset_details::{company_response, CacheFormat};
 use polygon_sdk::models::{CompanyDetails, Stock};
+use prost::Message;
 use sea_orm::DatabaseConnection;
 use services::stocks::get_stocks;
 use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
+use utils::cache::{company_details_key, company_response_key, stale_key, CachePolicy, InvalidationBatch};
 use utils::error::Error;
+use uuid::Uuid;
 
+/// The Pushgateway job the final metrics of each run are pushed under
+const METRICS_JOB: &str = "asset_details_ingestor";
+
+/// Exit code of a run stopped by SIGTERM or SIGINT, EX_TEMPFAIL as a retry resumes where it stopped
+const INTERRUPTED_EXIT_CODE: i32 = 75;
+
+/// Exit code of a malformed `migrate` subcommand, EX_USAGE
+const USAGE_EXIT_CODE: i32 = 64;
+
+/// Exit code of an invalid config, EX_CONFIG
+const CONFIG_EXIT_CODE: i32 = 78;
+
+/// How a run ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RunOutcome {
+    /// Every ticker was processed
+    Completed,
+    /// Stopped by a shutdown signal after finishing the current ticker, the next run resumes
+    /// after it
+    Interrupted,
+}
+
+/// Logging isn't set up until the config is loaded, so config errors go straight to stderr
+fn invalid_config<T>(e: Error) -> T {
+    eprintln!("{}", e.message);
+    std::process::exit(CONFIG_EXIT_CODE);
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    let app_state: IngestorState = config::load_state().await?;
+    // Logging isn't set up until the config is loaded, so usage errors go straight to stderr
+    let (migrate_command, args) = split_command(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e.message);
+        std::process::exit(USAGE_EXIT_CODE);
+    });
+
+    if let Some(migrate_command) = migrate_command {
+        let migrate_config: MigrateConfig = load_migrate_config::<IngestorConfig>(args).unwrap_or_else(invalid_config);
+        init_observability(&migrate_config.rust_log, &migrate_config.otel);
+
+        return run_migrate_command(&migrate_config.database, migrate_command).await;
+    }
+
+    let ingestor_config: IngestorConfig = load_config(args).unwrap_or_else(invalid_config);
+    init_observability(&ingestor_config.global.rust_log, &ingestor_config.global.otel);
+    tracing::info!(config = %redacted(&ingestor_config), "Loaded configuration");
+
+    let shutdown = Shutdown::listen();
+
+    let app_state: IngestorState = config::load_state(ingestor_config).await?;
+
+    // Every log line and span of the run carries its ID, so retried and overlapping runs can be
+    // told apart
+    let run_id = Uuid::now_v7();
+    let run_span = tracing::info_span!("ingestor.run", run_id = %run_id);
+
+    let started_at = Instant::now();
+    let run_result = run(&app_state, &shutdown).instrument(run_span).await;
+
+    let completed = matches!(run_result, Ok(RunOutcome::Completed));
+    metrics::gauge!("ingestor_run_duration_seconds").set(started_at.elapsed().as_secs_f64());
+    metrics::gauge!("ingestor_run_success").set(if completed { 1.0 } else { 0.0 });
+
+    if completed {
+        let finished_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        metrics::gauge!("ingestor_last_success_timestamp_seconds").set(finished_at.as_secs_f64());
+    }
+
+    // A batch run is gone before Prometheus could scrape it, so its final metrics are exported
+    if let Err(e) = app_state.global_state.metrics.export(METRICS_JOB).await {
+        tracing::error!("Failed to export metrics: {}", e);
+    }
+
+    shutdown_observability();
+
+    match run_result? {
+        RunOutcome::Completed => Ok(()),
+        RunOutcome::Interrupted => std::process::exit(INTERRUPTED_EXIT_CODE),
+    }
+}
 
-    let database_connection: DatabaseConnection = app_state.global_state.database_client;
+/// Ingest every listed company in ticker order, then invalidate and warm the cache. A shutdown
+/// signal stops the run once the current ticker is done, and the next run resumes after it.
+///
+/// # Errors
+///
+/// * If the stock listings cannot be fetched, returns a ThirdPartyError. Failures for single
+///   companies are logged and counted, and the run moves on.
+/// * If the checkpoint cannot be read or cleared, returns a DatabaseError
+async fn run(app_state: &IngestorState, shutdown: &Shutdown) -> Result<RunOutcome, Error> {
+    let database_connection: DatabaseConnection = app_state.global_state.database_client.clone();
 
     let cloudflare_api_key = app_state.cloudflare_api_key.clone();
     let cloudflare_account_id = app_state.cloudflare_account_id.clone();
@@ -27,71 +122,187 @@ async fn main() -> Result<(), Error> {
 
     let polygon_client = polygon_sdk::Client::new(&app_state.polygon_api_key);
 
-    let stocks: HashMap<String, Stock> = get_stocks(&polygon_client).await?;
+    let stocks: HashMap<String, Stock> = get_stocks(&polygon_client).await
+        .inspect(|_| record_polygon_call("list_stocks", "ok"))
+        .inspect_err(|_| record_polygon_call("list_stocks", "error"))?;
 
-    let total_length = stocks.len();
-    let mut progress = 0;
+    // Entries of upserted companies are invalidated in batches as the run progresses, which also
+    // clears any not found marker cached before the company existed. In the response format the
+    // fresh response is written instead, so the API never has to recompute it.
+    let mut invalidations: Option<InvalidationBatch> = app_state.cache.clone()
+        .map(|cache| InvalidationBatch::new(cache, app_state.invalidation_batch_size));
+
+    // Sorted so an interrupted run can resume after the last ticker it finished
+    let mut tickers: Vec<(&String, &Stock)> = stocks.iter().collect();
+    tickers.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut progress: usize = 0;
+
+    if let Some(checkpoint) = load_checkpoint(&database_connection).await? {
+        tracing::info!("Resuming after {}, {} tickers were processed before the interruption", checkpoint.last_ticker, checkpoint.processed);
+
+        tickers.retain(|(ticker, _)| ticker.as_str() > checkpoint.last_ticker.as_str());
+        progress = checkpoint.processed.max(0) as usize;
+    }
+
+    let total_length = progress + tickers.len();
+    let mut last_ticker: Option<&str> = None;
+    let mut outcome = RunOutcome::Completed;
+
+    for (ticker, stock) in tickers {
+        // Only checked between tickers, so a company is never left half ingested
+        if shutdown.requested() {
+            outcome = RunOutcome::Interrupted;
+            break;
+        }
 
-    for (ticker, stock) in stocks.iter() {
         let percentage = (progress as f64 / total_length as f64) * 100.0;
-        tracing::info!("Stock: {} - {} ({:.2}%)", ticker, stock.name, percentage);
-
-        let fetch_company_details = polygon_client.fetch_company_details(ticker)
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to fetch company details: {}", e);
-                Error::new(utils::error::ErrorType::ThirdPartyError, format!("Failed to fetch company details: {}", e))
-            });
-
-        let mut company_details: CompanyDetails = match fetch_company_details {
-            Ok(details) => details,
-            Err(e) => {
-                tracing::error!("Failed to fetch company details for {}: {}", ticker, e);
-                continue;
-            }
-        };
-
-        // Hackish but it's to make sure we don't store the private url
-        let company_branding = company_details.branding;
-        company_details.branding = None;
-
-        match company_branding {
-            Some(branding) => {
-                let branding_result = images::process_branding_images(
-                    &cloudflare_client,
-                    ticker.to_string(),
-                    branding,
-                    app_state.polygon_api_key.clone()
-                ).await;
-
-                match branding_result {
-                    Ok(new_branding) => {
-                        tracing::info!("Successfully processed branding images for {}", ticker);
-                        company_details.branding = Some(new_branding);
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to process branding images for {}: {}", ticker, e);
+
+        // Counted up front, as a failed ticker skips the rest of the iteration
+        progress += 1;
+        last_ticker = Some(ticker.as_str());
+
+        // Every log line about the ticker carries it, interleaved with the run's other output
+        let ticker_span = tracing::info_span!("ingestor.ticker", ticker = %ticker);
+
+        async {
+            tracing::info!("Stock: {} - {} ({:.2}%)", ticker, stock.name, percentage);
+
+            let fetch_company_details = polygon_client.fetch_company_details(ticker)
+                .await
+                .map_err(|e| {
+                    tracing::error!("Failed to fetch company details: {}", e);
+                    Error::new(utils::error::ErrorType::ThirdPartyError, format!("Failed to fetch company details: {}", e))
+                });
+
+            let mut company_details: CompanyDetails = match fetch_company_details {
+                Ok(details) => {
+                    record_polygon_call("company_details", "ok");
+                    details
+                }
+                Err(e) => {
+                    tracing::error!("Failed to fetch company details for {}: {}", ticker, e);
+                    record_polygon_call("company_details", "error");
+                    record_ticker("fetch_failed");
+                    return;
+                }
+            };
+
+            // Hackish but it's to make sure we don't store the private url
+            let company_branding = company_details.branding;
+            company_details.branding = None;
+
+            match company_branding {
+                Some(branding) => {
+                    let branding_result = images::process_branding_images(
+                        &cloudflare_client,
+                        ticker.to_string(),
+                        branding,
+                        app_state.polygon_api_key.clone()
+                    ).await;
+
+                    match branding_result {
+                        Ok(new_branding) => {
+                            tracing::info!("Successfully processed branding images for {}", ticker);
+                            company_details.branding = Some(new_branding);
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to process branding images for {}: {}", ticker, e);
+                        }
                     }
                 }
+                None => {
+                    tracing::info!("No branding images found for {}", ticker);
+                }
             }
-            None => {
-                tracing::info!("No branding images found for {}", ticker);
-            }
-        }
 
-        let insert_result = services::companies::find_existing_or_create(&database_connection, company_details).await;
+            let insert_result = services::companies::find_existing_or_create(&database_connection, company_details).await;
 
-        match insert_result {
-            Ok(_) => {
-                tracing::info!("Successfully inserted company details for {}", ticker);
+            match insert_result {
+                Ok(company) => {
+                    tracing::info!("Successfully inserted company details for {}", ticker);
+                    record_ticker("upserted");
+
+                    if let Some(invalidations) = invalidations.as_mut() {
+                        if let Err(e) = cache_company(invalidations, app_state.cache_format, &app_state.cache_policy, ticker, company).await {
+                            tracing::error!("Failed to invalidate cached company details: {}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Failed to insert company details for {}: {}", ticker, e);
+                    metrics::counter!("ingestor_upsert_failures_total").increment(1);
+                    record_ticker("upsert_failed");
+                }
             }
-            Err(e) => {
-                tracing::error!("Failed to insert company details for {}: {}", ticker, e);
+        }.instrument(ticker_span).await;
+    }
+
+    match (outcome, last_ticker) {
+        (RunOutcome::Interrupted, Some(last_ticker)) => {
+            match save_checkpoint(&database_connection, last_ticker, progress).await {
+                Ok(_) => tracing::info!("Interrupted after {} ({} of {} tickers), the next run resumes there", last_ticker, progress, total_length),
+                Err(e) => tracing::error!("Interrupted after {}, the next run starts over: {}", last_ticker, e),
             }
         }
+        // Nothing was processed, an earlier checkpoint still holds
+        (RunOutcome::Interrupted, None) => tracing::info!("Interrupted before processing any ticker"),
+        (RunOutcome::Completed, _) => clear_checkpoint(&database_connection).await?,
+    }
 
-        progress += 1;
+    if let Some(invalidations) = invalidations.as_mut() {
+        if let Err(e) = invalidations.flush().await {
+            tracing::error!("Failed to invalidate cached company details: {}", e);
+        }
+
+        tracing::info!("Invalidated {} cached company details", invalidations.invalidated());
+    }
+
+    if outcome == RunOutcome::Interrupted {
+        return Ok(outcome);
     }
 
-    Ok(())
+    // Refill the entries of the most requested companies, so the invalidations above don't send
+    // their next requests to the database
+    if let (Some(cache), Some(cache_warming)) = (&app_state.cache, &app_state.cache_warming) {
+        if let Err(e) = cache_warming.warm(&database_connection, cache, &app_state.cache_policy, app_state.cache_format).await {
+            tracing::error!("Failed to warm the cache: {}", e);
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Count a processed ticker by how far it got
+fn record_ticker(outcome: &'static str) {
+    metrics::counter!("ingestor_tickers_processed_total", "outcome" => outcome).increment(1);
+}
+
+fn record_polygon_call(operation: &'static str, outcome: &'static str) {
+    metrics::counter!("ingestor_polygon_requests_total", "operation" => operation, "outcome" => outcome).increment(1);
+}
+
+/// Queue the cache entries of an upserted company, the model entry is always invalidated and the
+/// response entry (with its stale copy) is either rewritten or invalidated depending on the API's
+/// cache format. Stale model copies are left to expire, they are only served while the database is
+/// down.
+///
+/// # Errors
+///
+/// * If a full batch cannot be invalidated, returns a CacheError
+async fn cache_company(invalidations: &mut InvalidationBatch, cache_format: CacheFormat, cache_policy: &CachePolicy, ticker: &str, company: Model) -> Result<(), Error> {
+    invalidations.push(company_details_key(ticker)).await?;
+
+    match cache_format {
+        CacheFormat::Response => {
+            let response = company_response(company).encode_to_vec();
+
+            if !cache_policy.stale_ttl.is_zero() {
+                invalidations.push_rewrite(stale_key(&company_response_key(ticker)), response.clone(), cache_policy.stale_ttl).await?;
+            }
+
+            invalidations.push_rewrite(company_response_key(ticker), response, cache_policy.jittered_ttl()).await
+        }
+        CacheFormat::Model => invalidations.push(company_response_key(ticker)).await,
+    }
 }
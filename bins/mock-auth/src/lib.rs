@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::fs;
+use serde::Deserialize;
+use tonic::{Request, Response, Status};
+use grpc::authentication::authentication::authentication_server::Authentication;
+use grpc::authentication::authentication::{TokenData, VerifyRequest, VerifyResponse};
+use grpc::request_id::REQUEST_ID_HEADER;
+use utils::error::{Error, ErrorType};
+
+/// How the mock server answers verification requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockMode {
+    /// Only tokens listed in the tokens file are accepted
+    File,
+    /// Every non-empty token is accepted with the default claims
+    AllowAll,
+    /// Every token is rejected
+    Deny,
+}
+
+impl TryFrom<&str> for MockMode {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "file" => Ok(MockMode::File),
+            "allow_all" => Ok(MockMode::AllowAll),
+            "deny" => Ok(MockMode::Deny),
+            _ => Err(Error::new(ErrorType::InvalidConfig, format!("Unknown mock auth mode: {}", value))),
+        }
+    }
+}
+
+/// A token accepted by the mock server, along with the claims returned for it. Field names match
+/// the `TokenData` message in `authentication.proto`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MockToken {
+    pub token: String,
+    pub aud: Vec<String>,
+    pub azp: String,
+    pub exp: u32,
+    pub iat: u32,
+    pub iss: String,
+    pub jti: String,
+    pub scope: String,
+    pub scp: Vec<String>,
+    pub sub: String,
+    pub permissions: Vec<String>,
+}
+
+impl From<MockToken> for TokenData {
+    fn from(token: MockToken) -> Self {
+        TokenData {
+            aud: token.aud,
+            azp: token.azp,
+            exp: token.exp,
+            iat: token.iat,
+            iss: token.iss,
+            jti: token.jti,
+            scope: token.scope,
+            scp: token.scp,
+            sub: token.sub,
+            permissions: token.permissions,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokensFile {
+    tokens: Vec<MockToken>,
+}
+
+/// Parse the accepted tokens from the contents of a tokens file
+///
+/// # Arguments
+///
+/// * `contents` - The JSON contents of the tokens file
+///
+/// # Returns
+///
+/// The accepted tokens keyed by token value
+///
+/// # Errors
+///
+/// * If the contents are not a valid tokens file, returns a ParseError
+pub fn parse_tokens(contents: &str) -> Result<HashMap<String, MockToken>, Error> {
+    let tokens_file: TokensFile = serde_json::from_str(contents).map_err(|e| {
+        Error::new(ErrorType::ParseError, format!("Invalid tokens file: {}", e))
+    })?;
+
+    Ok(tokens_file.tokens.into_iter().map(|token| (token.token.clone(), token)).collect())
+}
+
+/// Load the accepted tokens from a JSON file on disk
+///
+/// # Arguments
+///
+/// * `path` - The path to the tokens file
+///
+/// # Returns
+///
+/// The accepted tokens keyed by token value
+///
+/// # Errors
+///
+/// * If the file cannot be read or parsed, returns an error
+pub fn load_tokens(path: &str) -> Result<HashMap<String, MockToken>, Error> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        Error::new(ErrorType::MissingConfig, format!("Failed to read tokens file {}: {}", path, e))
+    })?;
+
+    parse_tokens(&contents)
+}
+
+/// Mock implementation of the Authentication service for local development and tests
+#[derive(Debug, Clone)]
+pub struct MockAuthService {
+    pub mode: MockMode,
+    pub tokens: HashMap<String, MockToken>,
+    /// Claims returned for every token in allow-all mode
+    pub default_token: MockToken,
+}
+
+impl MockAuthService {
+    fn rejected(message: &str) -> VerifyResponse {
+        VerifyResponse {
+            authenticated: false,
+            message: message.to_string(),
+            token_data: None,
+        }
+    }
+
+    fn accepted(token: MockToken) -> VerifyResponse {
+        VerifyResponse {
+            authenticated: true,
+            message: "Authenticated".to_string(),
+            token_data: Some(token.into()),
+        }
+    }
+
+    /// Decide whether a token is accepted, and with which claims
+    pub fn verify(&self, token: &str) -> VerifyResponse {
+        match self.mode {
+            MockMode::Deny => Self::rejected("Denied by mock auth"),
+            MockMode::AllowAll if token.is_empty() => Self::rejected("Empty token"),
+            MockMode::AllowAll => Self::accepted(MockToken {
+                token: token.to_string(),
+                ..self.default_token.clone()
+            }),
+            MockMode::File => match self.tokens.get(token) {
+                Some(mock_token) => Self::accepted(mock_token.clone()),
+                None => Self::rejected("Unknown token"),
+            },
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Authentication for MockAuthService {
+    async fn verify_token(&self, request: Request<VerifyRequest>) -> Result<Response<VerifyResponse>, Status> {
+        // The API forwards the ID of the call it is authenticating
+        let request_id = request.metadata().get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("none")
+            .to_string();
+
+        let token = request.into_inner().token;
+
+        let response = self.verify(&token);
+
+        tracing::info!(request_id = %request_id, "Mock verification in {:?} mode: authenticated={}", self.mode, response.authenticated);
+
+        Ok(Response::new(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(mode: MockMode) -> MockAuthService {
+        let tokens = parse_tokens(r#"{"tokens": [{"token": "known", "sub": "tester", "permissions": ["audit:read"]}]}"#).unwrap();
+
+        MockAuthService {
+            mode,
+            tokens,
+            default_token: MockToken {
+                sub: "anyone".to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_file_mode() {
+        let service = service(MockMode::File);
+
+        let accepted = service.verify("known");
+        assert!(accepted.authenticated);
+        let token_data = accepted.token_data.unwrap();
+        assert_eq!(token_data.sub, "tester");
+        assert_eq!(token_data.permissions, vec!["audit:read".to_string()]);
+
+        assert!(!service.verify("unknown").authenticated);
+    }
+
+    #[test]
+    fn test_allow_all_mode() {
+        let service = service(MockMode::AllowAll);
+
+        let accepted = service.verify("anything");
+        assert!(accepted.authenticated);
+        assert_eq!(accepted.token_data.unwrap().sub, "anyone");
+
+        assert!(!service.verify("").authenticated);
+    }
+
+    #[test]
+    fn test_deny_mode() {
+        let service = service(MockMode::Deny);
+
+        assert!(!service.verify("known").authenticated);
+    }
+
+    #[test]
+    fn test_example_tokens_file() {
+        let tokens = load_tokens(concat!(env!("CARGO_MANIFEST_DIR"), "/tokens.example.json")).unwrap();
+
+        assert!(tokens.contains_key("local-dev-token"));
+        assert!(tokens.contains_key("local-admin-token"));
+    }
+}
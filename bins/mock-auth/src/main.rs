@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use dotenvy::dotenv;
+use tonic::transport::Server;
+use grpc::authentication::authentication::authentication_server::AuthenticationServer;
+use mock_auth::{load_tokens, MockAuthService, MockMode, MockToken};
+use utils::env::get_optional_env_var;
+
+async fn start_server() -> Result<(), Box<dyn std::error::Error>> {
+    let load_env = dotenv();
+    if load_env.is_err() {
+        tracing::warn!("No .env file found");
+    }
+
+    let mode = MockMode::try_from(get_optional_env_var("MOCK_AUTH_MODE", "file".to_string()).as_str())?;
+
+    let tokens: HashMap<String, MockToken> = match mode {
+        MockMode::File => {
+            let tokens_file = get_optional_env_var("MOCK_AUTH_TOKENS_FILE", "tokens.json".to_string());
+            let tokens = load_tokens(&tokens_file)?;
+            tracing::info!("Loaded {} mock tokens from {}", tokens.len(), tokens_file);
+            tokens
+        }
+        _ => HashMap::new(),
+    };
+
+    // Claims handed out for every token in allow-all mode
+    let default_permissions: Vec<String> = get_optional_env_var("MOCK_AUTH_DEFAULT_PERMISSIONS", "".to_string())
+        .split(',')
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect();
+
+    let default_token = MockToken {
+        sub: get_optional_env_var("MOCK_AUTH_DEFAULT_SUBJECT", "mock-user".to_string()),
+        iss: "mock-auth".to_string(),
+        permissions: default_permissions,
+        ..Default::default()
+    };
+
+    let address: String = get_optional_env_var("ADDRESS", "0.0.0.0".to_string());
+    let port: String = get_optional_env_var("PORT", "5000".to_string());
+
+    let addr: SocketAddr = format!("{}:{}", address, port).parse()?;
+
+    tracing::info!("Starting mock auth server in {:?} mode on {}", mode, addr);
+
+    let mock_auth_service = MockAuthService {
+        mode,
+        tokens,
+        default_token,
+    };
+
+    Server::builder()
+        .add_service(AuthenticationServer::new(mock_auth_service))
+        .serve(addr)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_level(true)
+        .with_max_level(tracing::Level::INFO)
+        .event_format(
+            tracing_subscriber::fmt::format()
+                .with_file(true)
+                .with_line_number(true)
+        )
+        .json()
+        .init();
+
+    if let Err(e) = start_server().await {
+        tracing::error!("Error starting mock auth server: {:?}", e);
+        std::process::exit(1);
+    }
+}
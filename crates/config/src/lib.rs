@@ -1,106 +1,205 @@
 use std::time::Duration;
-use dotenvy::dotenv;
 use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use opentelemetry::trace::TracerProvider as _;
 use tracing::log;
-use utils::env::{get_optional_env_var, get_required_env_var};
-use migration;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use utils::error::{Error, ErrorType};
+
+pub mod layered;
+pub mod migrate;
+pub mod prometheus;
+pub mod settings;
+pub mod shutdown;
+pub mod telemetry;
+
+pub use layered::{check, load_config, redacted, Validate};
+pub use migrate::{load_migrate_config, run_migrate_command, split_command, MigrateCommand, MigrateConfig, MigrationPolicy};
+pub use prometheus::Metrics;
+pub use settings::{CacheBreakerConfig, CacheConfig, CacheWarmConfig, DatabaseConfig, GlobalConfig, MetricsConfig, OtelConfig};
+pub use shutdown::Shutdown;
+pub use telemetry::shutdown_observability;
 
 #[derive(Debug, Clone)]
 pub struct GlobalState {
+    /// The primary, used for writes, migrations and reads that must see the latest writes
     pub database_client: DatabaseConnection,
+    /// The read replica, or the primary when no replica is configured. Reads may lag the
+    /// primary by the replication delay.
+    pub read_client: DatabaseConnection,
+    pub metrics: Metrics,
 }
 
+/// Delay before the first retry of a failed connection, doubled after every further failure
+const CONNECT_INITIAL_DELAY: Duration = Duration::from_millis(500);
 
-/// Function to initialize the observability layer
+
+/// Function to initialize the observability layer, JSON logs plus span export to an OTLP
+/// collector when one is configured. Called once by each binary after its config is loaded.
 ///
 /// # Arguments
 ///
-/// * `log_level` - The log level to use for the application
+/// * `rust_log` - Which logs and spans are recorded, see [`parse_log_filter`]
+/// * `otel` - Where traces are exported
 ///
 /// # Returns
 ///
 /// * None
-fn init_observability(log_level: tracing::Level) {
-    tracing_subscriber::fmt()
-        .with_level(true)
-        .with_max_level(log_level)
-        .event_format(
-            tracing_subscriber::fmt::format()
+pub fn init_observability(rust_log: &str, otel: &OtelConfig) {
+    let log_filter: EnvFilter = parse_log_filter(rust_log);
+    let tracer_provider = telemetry::build_tracer_provider(otel);
+
+    let otel_layer = match &tracer_provider {
+        Ok(Some(provider)) => {
+            opentelemetry::global::set_tracer_provider(provider.clone());
+            Some(tracing_opentelemetry::layer().with_tracer(provider.tracer(telemetry::TRACER_NAME)))
+        }
+        _ => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(log_filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_level(true)
                 .with_file(true)
                 .with_line_number(true)
+                .json()
         )
-        .json()
+        .with(otel_layer)
         .init();
+
+    // Tracing is optional, a broken exporter shouldn't keep the binary from starting
+    match tracer_provider {
+        Ok(Some(_)) => tracing::info!("Exporting traces to {}", otel.exporter_otlp_endpoint),
+        Ok(None) => {}
+        Err(e) => tracing::error!("Traces are not exported: {}", e),
+    }
 }
 
 
-/// Function to initialize the Postgres database connection
+/// Parse a log filter, either a level such as `debug` or per-target directives such as
+/// `info,sqlx=warn`, falling back to INFO for anything that can't be parsed
+pub(crate) fn parse_log_filter(raw_log_filter: &str) -> EnvFilter {
+    // A bare word would otherwise be taken as a target to log everything from, e.g. `verbose`
+    let known_levels = !raw_log_filter.trim().is_empty() && raw_log_filter.split(',')
+        .map(|directive| directive.trim())
+        .all(|directive| directive.contains('=') || directive.parse::<LevelFilter>().is_ok());
+
+    EnvFilter::try_new(raw_log_filter)
+        .ok()
+        .filter(|_| known_levels)
+        .unwrap_or_else(|| EnvFilter::new(LevelFilter::INFO.to_string()))
+}
+
+
+/// Function to initialize a Postgres connection pool, retrying the first connection with
+/// exponential backoff so a database that is still starting doesn't crash the binary
 ///
 /// # Arguments
 ///
 /// * `database_url` - The URL to connect to the Postgres database
+/// * `config` - The pool and retry settings
 ///
 /// # Returns
 ///
 /// * A DatabaseConnection object
-async fn init_postgres(database_url: &str) -> DatabaseConnection {
+///
+/// # Errors
+///
+/// * If every connection attempt fails, returns a DatabaseError
+pub(crate) async fn init_postgres(database_url: &str, config: &DatabaseConfig) -> Result<DatabaseConnection, Error> {
     let mut opt = ConnectOptions::new(database_url);
-    opt.max_connections(1024)
-        .min_connections(5)
-        .connect_timeout(Duration::from_secs(8))
-        .acquire_timeout(Duration::from_secs(8))
-        .idle_timeout(Duration::from_secs(8))
-        .max_lifetime(Duration::from_secs(8))
+    opt.max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+        .acquire_timeout(Duration::from_millis(config.acquire_timeout_ms))
+        .idle_timeout(Duration::from_secs(config.idle_timeout_secs))
+        .max_lifetime(Duration::from_secs(config.max_lifetime_secs))
         .sqlx_logging(false)
         .sqlx_logging_level(log::LevelFilter::Info)
         .set_schema_search_path("public");
 
-    let database_pool: Result<DatabaseConnection, sea_orm::error::DbErr> = Database::connect(opt.clone()).await;
+    let mut delay: Duration = CONNECT_INITIAL_DELAY;
+    let mut attempt: u32 = 0;
 
-    match database_pool {
-        Ok(pool) => {
-            tracing::info!("Connected to Postgres");
+    loop {
+        attempt += 1;
 
-            migration::run_migrations(&pool).await;
+        match Database::connect(opt.clone()).await {
+            Ok(pool) => return Ok(pool),
+            Err(e) if attempt <= config.connect_retries => {
+                tracing::warn!("Error connecting to Postgres (attempt {}), retrying in {:?}: {}", attempt, delay, e);
 
-            pool
-        }
-        Err(e) => {
-            tracing::error!("Error connecting to Postgres: {:?}", e);
-            std::process::exit(1);
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_millis(config.connect_max_delay_ms));
+            }
+            Err(e) => {
+                return Err(Error::new(ErrorType::DatabaseError, format!("Error connecting to Postgres after {} attempts: {}", attempt, e)));
+            }
         }
     }
 }
 
-pub async fn load_state() -> GlobalState {
-    // Log configuration and bootstrap
-    let load_env = dotenv();
-    if load_env.is_err() {
-        tracing::warn!("No .env file found");
-    }
+/// Connect to the shared datastores and apply the migration policy to the primary, logging must
+/// already be initialised by [`init_observability`]
+///
+/// # Arguments
+///
+/// * `config` - The settings shared by every binary
+///
+/// # Returns
+///
+/// * The shared state, reads go to the replica when one is configured and to the primary if not
+///
+/// # Errors
+///
+/// * If the metrics recorder cannot be installed, returns an InvalidConfig error
+/// * If the primary or the replica cannot be reached, migrating fails or the schema is behind in
+///   verify only mode, returns a DatabaseError
+pub async fn load_state(config: &GlobalConfig) -> Result<GlobalState, Error> {
+    tracing::info!("Starting application with log filter: {}", parse_log_filter(&config.rust_log));
 
-    let raw_log_level: String = get_optional_env_var("RUST_LOG", "INFO".to_string());
-    let uppercased_log_level: String = raw_log_level.to_uppercase();
+    let metrics: Metrics = Metrics::install(&config.metrics)?;
 
-    let tracing_level: tracing::Level = match uppercased_log_level.as_str() {
-        "DEBUG" => tracing::Level::DEBUG,
-        "INFO" => tracing::Level::INFO,
-        "WARN" => tracing::Level::WARN,
-        "ERROR" => tracing::Level::ERROR,
-        _ => tracing::Level::INFO,
-    };
+    let database_client: DatabaseConnection = init_postgres(&config.database.url, &config.database).await?;
+    tracing::info!("Connected to Postgres");
 
-    init_observability(tracing_level);
+    let migration_policy: MigrationPolicy = MigrationPolicy::try_from(config.database.migration_policy.as_str())?;
+    migrate::apply_policy(&database_client, migration_policy).await?;
 
-    tracing::info!("Starting application with tracing level: {}", tracing_level);
-    
-    let database_url = get_required_env_var("DATABASE_URL");
+    let read_client: DatabaseConnection = match config.database.replica_url.as_str() {
+        "" => database_client.clone(),
+        replica_url => {
+            let read_client = init_postgres(replica_url, &config.database).await?;
+            tracing::info!("Connected to the Postgres read replica");
 
-    let database_client: DatabaseConnection = init_postgres(&database_url).await;
+            read_client
+        }
+    };
 
     let app_state: GlobalState = GlobalState {
         database_client,
+        read_client,
+        metrics,
     };
 
-    app_state
+    Ok(app_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_filter() {
+        assert_eq!(parse_log_filter("TRACE").max_level_hint(), Some(LevelFilter::TRACE));
+        assert_eq!(parse_log_filter("warn").max_level_hint(), Some(LevelFilter::WARN));
+        assert!(parse_log_filter("info,sqlx=warn").to_string().contains("sqlx=warn"));
+
+        // The old fallback for anything unknown
+        assert_eq!(parse_log_filter("loud").max_level_hint(), Some(LevelFilter::INFO));
+        assert_eq!(parse_log_filter("").max_level_hint(), Some(LevelFilter::INFO));
+    }
 }
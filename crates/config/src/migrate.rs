@@ -0,0 +1,287 @@
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+use serde_derive::Deserialize;
+use migration::{Direction, MigrationStatus};
+use utils::error::{Error, ErrorType};
+use crate::layered::{load_as, Validate};
+use crate::settings::{DatabaseConfig, OtelConfig};
+
+/// What a binary does about migrations on startup
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MigrationPolicy {
+    /// Apply pending migrations, replicas starting together take turns
+    Auto,
+    /// Refuse to start unless every migration is already applied, e.g. by the `migrate up`
+    /// subcommand run once per rollout
+    VerifyOnly,
+    /// Don't look at the schema at all
+    Skip,
+}
+
+impl TryFrom<&str> for MigrationPolicy {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(MigrationPolicy::Auto),
+            "verify_only" => Ok(MigrationPolicy::VerifyOnly),
+            "skip" => Ok(MigrationPolicy::Skip),
+            _ => Err(Error::new(ErrorType::InvalidConfig, format!("Unknown DATABASE_MIGRATION_POLICY: {}, expected auto, verify_only or skip", value))),
+        }
+    }
+}
+
+/// A `migrate` subcommand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MigrateCommand {
+    /// List every migration and whether it is applied
+    Status,
+    /// Apply every pending migration
+    Up,
+    /// Roll back the given number of migrations
+    Down(u32),
+    /// Print the SQL `up` or `down` would run without running it
+    DryRun(Direction, u32),
+}
+
+/// The only settings the `migrate` subcommand needs, the rest of the binary's settings are
+/// accepted but not validated, so migrating doesn't need e.g. the auth service or Redis configured
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MigrateConfig {
+    pub database: DatabaseConfig,
+    pub rust_log: String,
+    pub otel: OtelConfig,
+}
+
+impl Validate for MigrateConfig {
+    fn validate(&self, errors: &mut Vec<String>) {
+        self.database.validate(errors);
+    }
+}
+
+/// Load the settings of the `migrate` subcommand, from the same layers and flags as the binary's
+/// config `T` but validating only the database settings
+///
+/// # Arguments
+///
+/// * `args` - The command line, without the binary name or the subcommand
+///
+/// # Errors
+///
+/// * If any value is unknown, cannot be parsed, or a database setting fails validation, returns
+///   an InvalidConfig error listing all of them
+pub fn load_migrate_config<T: Default + Serialize>(args: impl IntoIterator<Item = String>) -> Result<MigrateConfig, Error> {
+    load_as::<T, MigrateConfig>(args)
+}
+
+fn database_error(context: &str, e: sea_orm::DbErr) -> Error {
+    Error::new(ErrorType::DatabaseError, format!("{}: {}", context, e))
+}
+
+/// Take a leading `migrate <status|up|down [steps]|dry-run [up|down [steps]]>` subcommand off the
+/// command line, leaving the config flags
+///
+/// # Arguments
+///
+/// * `args` - The command line, without the binary name
+///
+/// # Returns
+///
+/// The subcommand if there is one, and the remaining arguments
+///
+/// # Errors
+///
+/// * If the subcommand is unknown or its step count is invalid, returns an InvalidConfig error
+pub fn split_command(args: impl IntoIterator<Item = String>) -> Result<(Option<MigrateCommand>, Vec<String>), Error> {
+    let mut args: Vec<String> = args.into_iter().collect();
+
+    if args.first().map(String::as_str) != Some("migrate") {
+        return Ok((None, args));
+    }
+
+    let usage = || Error::new(ErrorType::InvalidConfig, "Usage: migrate <status|up|down [steps]|dry-run [up|down [steps]]> [--flags]".to_string());
+
+    let mut operands: Vec<String> = args.drain(..args.iter().position(|arg| arg.starts_with("--")).unwrap_or(args.len())).skip(1).collect();
+    operands.reverse();
+
+    let steps = |operand: Option<String>| -> Result<u32, Error> {
+        match operand {
+            None => Ok(1),
+            Some(raw) => raw.parse::<u32>().ok().filter(|steps| *steps > 0).ok_or_else(usage),
+        }
+    };
+
+    let command = match operands.pop().as_deref() {
+        Some("status") => MigrateCommand::Status,
+        Some("up") => MigrateCommand::Up,
+        Some("down") => MigrateCommand::Down(steps(operands.pop())?),
+        Some("dry-run") => match operands.pop().as_deref() {
+            None | Some("up") => MigrateCommand::DryRun(Direction::Up, 0),
+            Some("down") => MigrateCommand::DryRun(Direction::Down, steps(operands.pop())?),
+            Some(_) => return Err(usage()),
+        },
+        _ => return Err(usage()),
+    };
+
+    if !operands.is_empty() {
+        return Err(usage());
+    }
+
+    Ok((Some(command), args))
+}
+
+/// Fail unless every migration this binary knows of is applied
+///
+/// # Errors
+///
+/// * If the schema is behind or cannot be read, returns a DatabaseError
+async fn verify_schema(database: &DatabaseConnection) -> Result<(), Error> {
+    let pending: Vec<String> = migration::pending_migrations(database).await
+        .map_err(|e| database_error("Failed to read the applied migrations", e))?;
+
+    match pending.is_empty() {
+        true => Ok(()),
+        false => Err(Error::new(ErrorType::DatabaseError, format!(
+            "Schema is behind the expected version {}, {} pending migrations: {}. Run the migrate up subcommand first",
+            migration::expected_version(),
+            pending.len(),
+            pending.join(", "),
+        ))),
+    }
+}
+
+/// Apply the migration policy on startup
+///
+/// # Errors
+///
+/// * If migrating fails or the schema is behind in verify only mode, returns a DatabaseError
+pub(crate) async fn apply_policy(database: &DatabaseConnection, policy: MigrationPolicy) -> Result<(), Error> {
+    match policy {
+        MigrationPolicy::Auto => migration::run_migrations(database).await
+            .map_err(|e| database_error("Failed to apply migrations", e)),
+        MigrationPolicy::VerifyOnly => {
+            verify_schema(database).await?;
+            tracing::info!("Schema is at the expected version {}", migration::expected_version());
+            Ok(())
+        }
+        MigrationPolicy::Skip => {
+            tracing::warn!("Skipping migrations, the schema is not checked");
+            Ok(())
+        }
+    }
+}
+
+/// Run a `migrate` subcommand against the primary, printing its result
+///
+/// # Arguments
+///
+/// * `config` - The database settings
+/// * `command` - The subcommand to run
+///
+/// # Errors
+///
+/// * If the primary cannot be reached or a migration fails, returns a DatabaseError
+pub async fn run_migrate_command(config: &DatabaseConfig, command: MigrateCommand) -> Result<(), Error> {
+    let database: DatabaseConnection = crate::init_postgres(&config.url, config).await?;
+
+    match command {
+        MigrateCommand::Status => {
+            let statuses: Vec<(String, MigrationStatus)> = migration::migration_status(&database).await
+                .map_err(|e| database_error("Failed to read the applied migrations", e))?;
+
+            for (name, status) in statuses {
+                println!("{} {}", status, name);
+            }
+        }
+        MigrateCommand::Up => {
+            migration::run_migrations(&database).await.map_err(|e| database_error("Failed to apply migrations", e))?;
+            println!("Schema is at {}", migration::expected_version());
+        }
+        MigrateCommand::Down(steps) => {
+            migration::rollback_migrations(&database, steps).await.map_err(|e| database_error("Failed to roll back migrations", e))?;
+            println!("Rolled back {} migrations", steps);
+        }
+        MigrateCommand::DryRun(direction, steps) => {
+            let plan: Vec<(String, Vec<String>)> = migration::dry_run(&database, direction, steps).await
+                .map_err(|e| database_error("Failed to plan migrations", e))?;
+
+            if plan.is_empty() {
+                println!("-- Nothing to run");
+            }
+
+            for (name, statements) in plan {
+                println!("-- {}", name);
+
+                for statement in statements {
+                    println!("{};", statement.trim());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layered::{finish, merge_layers};
+    use crate::settings::GlobalConfig;
+
+    fn split(args: &[&str]) -> Result<(Option<MigrateCommand>, Vec<String>), Error> {
+        split_command(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(split(&["--port", "1"]).unwrap(), (None, vec!["--port".to_string(), "1".to_string()]));
+        assert_eq!(split(&["migrate", "status"]).unwrap().0, Some(MigrateCommand::Status));
+        assert_eq!(split(&["migrate", "down"]).unwrap().0, Some(MigrateCommand::Down(1)));
+        assert_eq!(split(&["migrate", "dry-run"]).unwrap().0, Some(MigrateCommand::DryRun(Direction::Up, 0)));
+
+        let (command, rest) = split(&["migrate", "dry-run", "down", "2", "--config", "api.toml"]).unwrap();
+        assert_eq!(command, Some(MigrateCommand::DryRun(Direction::Down, 2)));
+        assert_eq!(rest, vec!["--config".to_string(), "api.toml".to_string()]);
+
+        assert!(split(&["migrate"]).is_err());
+        assert!(split(&["migrate", "down", "0"]).is_err());
+        assert!(split(&["migrate", "up", "extra"]).is_err());
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    #[serde(default)]
+    struct BinaryConfig {
+        #[serde(flatten)]
+        global: GlobalConfig,
+        auth_url: String,
+    }
+
+    impl Validate for BinaryConfig {
+        fn validate(&self, errors: &mut Vec<String>) {
+            self.global.validate(errors);
+
+            if self.auth_url.is_empty() {
+                errors.push("AUTH_URL is required".to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn test_migrate_only_validates_the_database() {
+        let env = |name: &str| (name == "DATABASE_URL").then(|| "postgres://localhost/db".to_string());
+
+        let (merged, errors) = merge_layers::<BinaryConfig>(vec!["--auth-url=".to_string()], &env);
+        assert!(finish::<BinaryConfig>(merged.clone(), errors.clone()).is_err());
+
+        let config = finish::<MigrateConfig>(merged, errors).unwrap();
+        assert_eq!(config.database.url, "postgres://localhost/db");
+
+        // Flags of the rest of the binary's config are still checked
+        let (merged, errors) = merge_layers::<BinaryConfig>(vec!["--cache-url=redis://cache".to_string()], &env);
+        assert!(finish::<MigrateConfig>(merged, errors).is_err());
+
+        let (merged, errors) = merge_layers::<BinaryConfig>(Vec::new(), &|_| None);
+        assert_eq!(finish::<MigrateConfig>(merged, errors).unwrap_err(), vec!["DATABASE_URL is required".to_string()]);
+    }
+}
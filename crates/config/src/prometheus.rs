@@ -0,0 +1,132 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use utils::error::{Error, ErrorType};
+use crate::settings::MetricsConfig;
+
+/// Histogram buckets in seconds, from sub-millisecond cache hits to the slowest third party calls
+const LATENCY_BUCKETS: [f64; 14] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// How often histogram samples are drained into their buckets
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The installed Prometheus recorder
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    handle: PrometheusHandle,
+    config: MetricsConfig,
+}
+
+fn metrics_error(e: impl std::fmt::Display) -> Error {
+    Error::new(ErrorType::InvalidConfig, format!("Unable to set up metrics: {}", e))
+}
+
+impl Metrics {
+    /// Install the global Prometheus recorder, serving `/metrics` on its own port unless the port
+    /// is 0. Must be called once, inside the Tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Where metrics are exposed
+    ///
+    /// # Errors
+    ///
+    /// * If a recorder is already installed or the listener address is invalid, returns an
+    ///   InvalidConfig error
+    pub fn install(config: &MetricsConfig) -> Result<Self, Error> {
+        let mut builder = PrometheusBuilder::new()
+            .set_buckets(&LATENCY_BUCKETS)
+            .map_err(metrics_error)?;
+
+        if config.port != 0 {
+            let address: IpAddr = config.address.parse().map_err(metrics_error)?;
+            builder = builder.with_http_listener(SocketAddr::new(address, config.port));
+        }
+
+        let (recorder, exporter) = builder.build().map_err(metrics_error)?;
+        let handle = recorder.handle();
+
+        metrics::set_global_recorder(recorder).map_err(metrics_error)?;
+
+        if config.port != 0 {
+            tracing::info!("Serving metrics on {}:{}", config.address, config.port);
+
+            tokio::spawn(async move {
+                if let Err(e) = exporter.await {
+                    tracing::error!("Metrics listener stopped: {:?}", e);
+                }
+            });
+        }
+
+        let upkeep_handle = handle.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(UPKEEP_INTERVAL);
+
+            loop {
+                interval.tick().await;
+                upkeep_handle.run_upkeep();
+            }
+        });
+
+        Ok(Metrics {
+            handle,
+            config: config.clone(),
+        })
+    }
+
+    /// The current metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        self.handle.render()
+    }
+
+    /// Export the final metrics of a batch run, to the textfile and the Pushgateway when
+    /// configured. Both are attempted even if the other fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `job` - The Pushgateway job name
+    ///
+    /// # Errors
+    ///
+    /// * If the textfile cannot be written or the push fails, returns an error
+    pub async fn export(&self, job: &str) -> Result<(), Error> {
+        let rendered: String = self.render();
+        let mut errors: Vec<String> = Vec::new();
+
+        if !self.config.textfile_path.is_empty() {
+            // Written to a temporary file first so the collector never reads a partial file
+            let temporary_path = format!("{}.tmp", self.config.textfile_path);
+
+            let written = std::fs::write(&temporary_path, &rendered)
+                .and_then(|_| std::fs::rename(&temporary_path, &self.config.textfile_path));
+
+            match written {
+                Ok(_) => tracing::info!("Wrote metrics to {}", self.config.textfile_path),
+                Err(e) => errors.push(format!("Unable to write metrics to {}: {}", self.config.textfile_path, e)),
+            }
+        }
+
+        if !self.config.push_url.is_empty() {
+            let push_url = format!("{}/metrics/job/{}", self.config.push_url.trim_end_matches('/'), job);
+
+            let pushed = reqwest::Client::new()
+                .put(&push_url)
+                .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(rendered)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            match pushed {
+                Ok(_) => tracing::info!("Pushed metrics for job {}", job),
+                // The URL may carry credentials
+                Err(e) => errors.push(format!("Unable to push metrics: {}", e.without_url())),
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(Error::new(ErrorType::ThirdPartyError, errors.join("; "))),
+        }
+    }
+}
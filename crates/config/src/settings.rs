@@ -0,0 +1,396 @@
+use std::time::Duration;
+use serde_derive::{Deserialize, Serialize};
+use utils::cache::{parse_redis_target, BreakerSettings, CacheCodec, CachePolicy, CacheSettings, Compression, RedisTarget, RedisTopology};
+use utils::error::{Error, ErrorType};
+use crate::layered::{check, Validate};
+use crate::migrate::MigrationPolicy;
+
+/// Settings shared by every binary, flattened into each binary's config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GlobalConfig {
+    /// A level such as INFO, or per-target directives such as `info,sqlx=warn`. Anything that
+    /// can't be parsed falls back to INFO.
+    pub rust_log: String,
+    pub database: DatabaseConfig,
+    pub metrics: MetricsConfig,
+    pub otel: OtelConfig,
+}
+
+impl Default for GlobalConfig {
+    fn default() -> Self {
+        GlobalConfig {
+            rust_log: "INFO".to_string(),
+            database: DatabaseConfig::default(),
+            metrics: MetricsConfig::default(),
+            otel: OtelConfig::default(),
+        }
+    }
+}
+
+impl Validate for GlobalConfig {
+    fn validate(&self, errors: &mut Vec<String>) {
+        self.database.validate(errors);
+        self.metrics.validate(errors);
+        self.otel.validate(errors);
+    }
+}
+
+/// Where Prometheus metrics are exposed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Address of the HTTP listener serving `/metrics`
+    pub address: String,
+    /// Port of the HTTP listener, 0 disables it
+    pub port: u16,
+    /// Optional Pushgateway URL that batch runs push their final metrics to
+    pub push_url: String,
+    /// Optional file that batch runs write their final metrics to, for the node exporter's
+    /// textfile collector
+    pub textfile_path: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            address: "0.0.0.0".to_string(),
+            port: 9090,
+            push_url: String::new(),
+            textfile_path: String::new(),
+        }
+    }
+}
+
+impl Validate for MetricsConfig {
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.address.parse::<std::net::IpAddr>().is_err() {
+            errors.push(format!("METRICS_ADDRESS must be an IP address, got '{}'", self.address));
+        }
+
+        if !self.push_url.is_empty() {
+            match url::Url::parse(&self.push_url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {}
+                _ => errors.push(format!("METRICS_PUSH_URL must be an http:// or https:// URL, got '{}'", self.push_url)),
+            }
+        }
+    }
+}
+
+/// Where traces are exported, named so the environment variables match the OpenTelemetry ones
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OtelConfig {
+    /// gRPC endpoint of an OTLP collector, e.g. http://localhost:4317. Empty disables export.
+    pub exporter_otlp_endpoint: String,
+    /// The `service.name` of exported spans, defaults to the binary's name
+    pub service_name: String,
+    /// Fraction of new traces sampled, between 0 and 1. Traces started by a caller follow the
+    /// caller's sampling decision.
+    pub traces_sampler_arg: f64,
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        OtelConfig {
+            exporter_otlp_endpoint: String::new(),
+            service_name: String::new(),
+            traces_sampler_arg: 1.0,
+        }
+    }
+}
+
+impl Validate for OtelConfig {
+    fn validate(&self, errors: &mut Vec<String>) {
+        if !self.exporter_otlp_endpoint.is_empty() {
+            match url::Url::parse(&self.exporter_otlp_endpoint) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {}
+                _ => errors.push(format!("OTEL_EXPORTER_OTLP_ENDPOINT must be an http:// or https:// URL, got '{}'", self.exporter_otlp_endpoint)),
+            }
+        }
+
+        if !(0.0..=1.0).contains(&self.traces_sampler_arg) {
+            errors.push(format!("OTEL_TRACES_SAMPLER_ARG must be between 0 and 1, got {}", self.traces_sampler_arg));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// A postgres:// or postgresql:// URL of the primary
+    pub url: String,
+    /// Optional postgres:// URL of a read replica, company lookups are served from it when set
+    pub replica_url: String,
+    /// Company queries taking longer than this fail over to the stale cached copy, 0 disables the
+    /// limit
+    pub query_timeout_ms: u64,
+    /// Pool size limits, applied to the primary and the replica separately
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub connect_timeout_ms: u64,
+    /// How long a query waits for a free connection from the pool
+    pub acquire_timeout_ms: u64,
+    /// Idle connections above `min_connections` are closed after this long
+    pub idle_timeout_secs: u64,
+    /// Connections are recycled after this long, e.g. to pick up DNS changes after a failover
+    pub max_lifetime_secs: u64,
+    /// How many times to retry the first connection, with exponential backoff, before giving up
+    pub connect_retries: u32,
+    /// Upper bound on the delay between connection attempts
+    pub connect_max_delay_ms: u64,
+    /// auto, verify_only or skip, see [`MigrationPolicy`]
+    pub migration_policy: String,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            replica_url: String::new(),
+            query_timeout_ms: 2000,
+            max_connections: 100,
+            min_connections: 5,
+            connect_timeout_ms: 8000,
+            acquire_timeout_ms: 8000,
+            idle_timeout_secs: 600,
+            max_lifetime_secs: 1800,
+            connect_retries: 5,
+            connect_max_delay_ms: 10000,
+            migration_policy: "auto".to_string(),
+        }
+    }
+}
+
+impl DatabaseConfig {
+    pub fn query_timeout(&self) -> Option<Duration> {
+        match self.query_timeout_ms {
+            0 => None,
+            query_timeout_ms => Some(Duration::from_millis(query_timeout_ms)),
+        }
+    }
+}
+
+impl Validate for DatabaseConfig {
+    fn validate(&self, errors: &mut Vec<String>) {
+        match self.url.is_empty() {
+            true => errors.push("DATABASE_URL is required".to_string()),
+            false => check_postgres_url(errors, "DATABASE_URL", &self.url),
+        }
+
+        if !self.replica_url.is_empty() {
+            check_postgres_url(errors, "DATABASE_REPLICA_URL", &self.replica_url);
+        }
+
+        check(errors, MigrationPolicy::try_from(self.migration_policy.as_str()));
+
+        if self.max_connections == 0 || self.min_connections > self.max_connections {
+            errors.push("DATABASE_MAX_CONNECTIONS must be greater than zero and at least DATABASE_MIN_CONNECTIONS".to_string());
+        }
+    }
+}
+
+fn check_postgres_url(errors: &mut Vec<String>, name: &str, raw_url: &str) {
+    match url::Url::parse(raw_url) {
+        Ok(parsed) if matches!(parsed.scheme(), "postgres" | "postgresql") => {}
+        Ok(parsed) => errors.push(format!("{} must be a postgres:// URL, got the {} scheme", name, parsed.scheme())),
+        Err(e) => errors.push(format!("Invalid {}: {}", name, e)),
+    }
+}
+
+/// Cache settings shared by the API and the ingestor, so both agree on how entries are written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// redis, memory (single replica, no Redis needed) or tiered (memory in front of redis)
+    pub backend: String,
+    pub memory_capacity: usize,
+    /// How long a replica may serve a value from memory before re-reading it from Redis
+    pub l1_ttl_secs: u64,
+    pub ttl_secs: u64,
+    /// Fraction of the TTL, between 0 and 1, randomly added or removed per entry
+    pub ttl_jitter: f64,
+    /// When above 0, only one replica recomputes a missing entry while holding a lock this long
+    pub recompute_lock_ms: u64,
+    pub negative_ttl_secs: u64,
+    pub stale_ttl_secs: u64,
+    pub refresh_ahead_secs: u64,
+    /// lz4 or none
+    pub compression: String,
+    pub compression_threshold_bytes: usize,
+    /// model or response
+    pub format: String,
+    /// A server URL for standalone, seed nodes for a cluster or the sentinels for sentinel, comma
+    /// separated
+    pub url: String,
+    /// Overrides the password in the URL when set
+    pub password: String,
+    /// standalone, cluster or sentinel
+    pub topology: String,
+    pub sentinel_master: String,
+    pub response_timeout_ms: u64,
+    pub connection_timeout_ms: u64,
+    pub reconnect_retries: usize,
+    pub reconnect_max_delay_ms: u64,
+    /// Number of upserted companies whose entries the ingestor invalidates together
+    pub invalidation_batch_size: usize,
+    /// Lets an ingestor run go ahead without invalidating the cache when Redis is unreachable,
+    /// otherwise the run fails
+    pub invalidation_optional: bool,
+    pub warm: CacheWarmConfig,
+    pub breaker: CacheBreakerConfig,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        let policy = CachePolicy::default();
+        let settings = CacheSettings::default();
+
+        CacheConfig {
+            backend: "redis".to_string(),
+            memory_capacity: 10000,
+            l1_ttl_secs: 60,
+            ttl_secs: policy.ttl.as_secs(),
+            ttl_jitter: policy.ttl_jitter,
+            recompute_lock_ms: 0,
+            negative_ttl_secs: policy.negative_ttl.as_secs(),
+            stale_ttl_secs: policy.stale_ttl.as_secs(),
+            refresh_ahead_secs: policy.refresh_ahead.as_secs(),
+            compression: "lz4".to_string(),
+            compression_threshold_bytes: policy.codec.compression_threshold,
+            format: "model".to_string(),
+            url: String::new(),
+            password: String::new(),
+            topology: "standalone".to_string(),
+            sentinel_master: String::new(),
+            response_timeout_ms: settings.response_timeout.as_millis() as u64,
+            connection_timeout_ms: settings.connection_timeout.as_millis() as u64,
+            reconnect_retries: settings.reconnect_retries,
+            reconnect_max_delay_ms: settings.reconnect_max_delay.as_millis() as u64,
+            invalidation_batch_size: 100,
+            invalidation_optional: false,
+            warm: CacheWarmConfig::default(),
+            breaker: CacheBreakerConfig::default(),
+        }
+    }
+}
+
+/// Which companies are preloaded into the cache, a symbol list takes precedence over the top N
+/// by market cap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheWarmConfig {
+    pub symbols: Vec<String>,
+    pub top_n: u64,
+    pub concurrency: usize,
+}
+
+impl Default for CacheWarmConfig {
+    fn default() -> Self {
+        CacheWarmConfig {
+            symbols: Vec::new(),
+            top_n: 0,
+            concurrency: 8,
+        }
+    }
+}
+
+/// When the circuit breaker in front of Redis opens and for how long
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheBreakerConfig {
+    pub failure_threshold: u32,
+    pub open_ms: u64,
+}
+
+impl Default for CacheBreakerConfig {
+    fn default() -> Self {
+        let settings = BreakerSettings::default();
+
+        CacheBreakerConfig {
+            failure_threshold: settings.failure_threshold,
+            open_ms: settings.open_duration.as_millis() as u64,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// How cached entries are written and recomputed
+    ///
+    /// # Errors
+    ///
+    /// * If the jitter is outside 0 to 1 or the compression is unknown, returns an InvalidConfig
+    ///   error
+    pub fn policy(&self) -> Result<CachePolicy, Error> {
+        if !(0.0..=1.0).contains(&self.ttl_jitter) {
+            return Err(Error::new(ErrorType::InvalidConfig, "CACHE_TTL_JITTER must be between 0 and 1".to_string()));
+        }
+
+        Ok(CachePolicy {
+            ttl: Duration::from_secs(self.ttl_secs),
+            ttl_jitter: self.ttl_jitter,
+            recompute_lock_ttl: match self.recompute_lock_ms {
+                0 => None,
+                recompute_lock_ms => Some(Duration::from_millis(recompute_lock_ms)),
+            },
+            negative_ttl: Duration::from_secs(self.negative_ttl_secs),
+            codec: CacheCodec {
+                compression: Compression::try_from(self.compression.as_str())?,
+                compression_threshold: self.compression_threshold_bytes,
+            },
+            stale_ttl: Duration::from_secs(self.stale_ttl_secs),
+            refresh_ahead: Duration::from_secs(self.refresh_ahead_secs),
+        })
+    }
+
+    /// The Redis connection timeouts and retries
+    pub fn connection_settings(&self) -> CacheSettings {
+        CacheSettings {
+            response_timeout: Duration::from_millis(self.response_timeout_ms),
+            connection_timeout: Duration::from_millis(self.connection_timeout_ms),
+            reconnect_retries: self.reconnect_retries,
+            reconnect_max_delay: Duration::from_millis(self.reconnect_max_delay_ms),
+        }
+    }
+
+    pub fn breaker_settings(&self) -> BreakerSettings {
+        BreakerSettings {
+            failure_threshold: self.breaker.failure_threshold.max(1),
+            open_duration: Duration::from_millis(self.breaker.open_ms),
+        }
+    }
+
+    /// Where and how to connect to Redis
+    ///
+    /// # Returns
+    ///
+    /// The Redis connection target, or None if no URL is configured
+    ///
+    /// # Errors
+    ///
+    /// * If the URL is invalid or doesn't suit the topology, returns an InvalidConfig error
+    pub fn redis_target(&self) -> Result<Option<RedisTarget>, Error> {
+        if self.url.is_empty() {
+            return Ok(None);
+        }
+
+        let topology: RedisTopology = RedisTopology::from_config(&self.topology, &self.sentinel_master)?;
+
+        parse_redis_target(&self.url, Some(self.password.clone()), topology).map(Some)
+    }
+}
+
+impl Validate for CacheConfig {
+    fn validate(&self, errors: &mut Vec<String>) {
+        check(errors, self.policy());
+        check(errors, self.redis_target());
+
+        if !matches!(self.backend.as_str(), "redis" | "memory" | "tiered") {
+            errors.push(format!("Unknown CACHE_BACKEND: {}", self.backend));
+        }
+
+        if self.invalidation_batch_size == 0 {
+            errors.push("CACHE_INVALIDATION_BATCH_SIZE must be greater than zero".to_string());
+        }
+    }
+}
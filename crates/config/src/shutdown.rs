@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Resolves once the process receives SIGTERM or SIGINT, returning the signal's name
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Unable to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = terminate => "SIGTERM",
+    }
+}
+
+/// Shared flag set once the binary is asked to stop, cloned into everything that has to wind
+/// down
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown {
+            sender: Arc::new(watch::channel(false).0),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Start listening for SIGTERM and SIGINT in the background. Must be called inside the Tokio
+    /// runtime.
+    pub fn listen() -> Self {
+        let shutdown = Shutdown::default();
+        let signalled = shutdown.clone();
+
+        tokio::spawn(async move {
+            let signal = shutdown_signal().await;
+            tracing::info!("Received {}, shutting down", signal);
+
+            signalled.trigger();
+        });
+
+        shutdown
+    }
+
+    /// Ask everything holding a clone to stop, as if a signal was received
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Whether shutting down has been asked for
+    pub fn requested(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once shutting down has been asked for, immediately if it already has
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+
+        // The sender lives as long as self, so this only returns once the flag is set
+        let _ = receiver.wait_for(|requested| *requested).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_wait_resolves_once_triggered() {
+        let shutdown = Shutdown::default();
+        assert!(!shutdown.requested());
+
+        let waiting = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+
+        assert!(shutdown.requested());
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait()).await.unwrap();
+    }
+}
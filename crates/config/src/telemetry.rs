@@ -0,0 +1,159 @@
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::{Sampler, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use utils::error::{Error, ErrorType};
+use crate::settings::OtelConfig;
+
+/// Name of the instrumentation scope every span is exported under
+pub(crate) const TRACER_NAME: &str = "asset-details";
+
+/// The configured service name, or the name of the running binary
+fn service_name(config: &OtelConfig) -> String {
+    if !config.service_name.is_empty() {
+        return config.service_name.clone();
+    }
+
+    std::env::current_exe().ok()
+        .and_then(|path| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+        .unwrap_or_else(|| TRACER_NAME.to_string())
+}
+
+/// Build a tracer provider exporting spans in batches to an OTLP collector over gRPC. Must be
+/// called inside the Tokio runtime.
+///
+/// # Arguments
+///
+/// * `config` - Where traces are exported
+///
+/// # Returns
+///
+/// * The provider, or None if no collector endpoint is configured
+///
+/// # Errors
+///
+/// * If the exporter cannot be built, returns an InvalidConfig error
+pub(crate) fn build_tracer_provider(config: &OtelConfig) -> Result<Option<TracerProvider>, Error> {
+    if config.exporter_otlp_endpoint.is_empty() {
+        return Ok(None);
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(config.exporter_otlp_endpoint.clone())
+        .build()
+        .map_err(|e| Error::new(ErrorType::InvalidConfig, format!("Unable to set up trace export: {}", e)))?;
+
+    // Follow the caller's decision when a request carries a trace context, so traces are never
+    // cut in half, and sample new traces at the configured ratio
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.traces_sampler_arg)));
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_sampler(sampler)
+        .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name(config))]))
+        .build();
+
+    Ok(Some(provider))
+}
+
+/// Flush spans and logs that are still buffered and stop exporting, call once before the binary
+/// exits
+pub fn shutdown_observability() {
+    opentelemetry::global::shutdown_tracer_provider();
+
+    let _ = std::io::Write::flush(&mut std::io::stdout());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, TracerProvider as _};
+    use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{TraceService, TraceServiceServer};
+    use opentelemetry_proto::tonic::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse};
+    use opentelemetry_proto::tonic::common::v1::any_value::Value;
+    use tokio::sync::mpsc;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Stands in for an OTLP collector, forwarding every export request to the test
+    struct TestCollector {
+        requests: mpsc::UnboundedSender<ExportTraceServiceRequest>,
+    }
+
+    #[tonic::async_trait]
+    impl TraceService for TestCollector {
+        async fn export(&self, request: tonic::Request<ExportTraceServiceRequest>) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+            let _ = self.requests.send(request.into_inner());
+
+            Ok(tonic::Response::new(ExportTraceServiceResponse { partial_success: None }))
+        }
+    }
+
+    /// Start a collector on a random local port and return its endpoint
+    async fn start_collector() -> (String, mpsc::UnboundedReceiver<ExportTraceServiceRequest>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(Server::builder()
+            .add_service(TraceServiceServer::new(TestCollector { requests: sender }))
+            .serve_with_incoming(incoming));
+
+        (format!("http://{}", address), receiver)
+    }
+
+    #[test]
+    fn test_disabled_without_endpoint() {
+        assert!(build_tracer_provider(&OtelConfig::default()).unwrap().is_none());
+    }
+
+    // The batch exporter is flushed synchronously, which needs a second worker thread
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_spans_are_exported_under_the_callers_trace() {
+        let (endpoint, mut requests) = start_collector().await;
+
+        let provider = build_tracer_provider(&OtelConfig {
+            exporter_otlp_endpoint: endpoint,
+            service_name: "asset-details-test".to_string(),
+            traces_sampler_arg: 0.0,
+        }).unwrap().unwrap();
+
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(TRACER_NAME)));
+
+        // A sampled caller, which must win over the ratio of 0
+        let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+        let caller = SpanContext::new(trace_id, SpanId::from_hex("00f067aa0ba902b7").unwrap(), TraceFlags::SAMPLED, true, TraceState::default());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("cache.lookup", symbol = "AAPL");
+            span.set_parent(opentelemetry::Context::new().with_remote_span_context(caller));
+            let _entered = span.enter();
+        });
+
+        for result in provider.force_flush() {
+            result.unwrap();
+        }
+
+        let request = requests.recv().await.unwrap();
+        let resource_spans = &request.resource_spans[0];
+
+        let service_name = resource_spans.resource.as_ref().unwrap().attributes.iter()
+            .find(|attribute| attribute.key == "service.name")
+            .and_then(|attribute| attribute.value.as_ref()?.value.clone());
+        assert_eq!(service_name, Some(Value::StringValue("asset-details-test".to_string())));
+
+        let span = &resource_spans.scope_spans[0].spans[0];
+        assert_eq!(span.name, "cache.lookup");
+        assert_eq!(span.trace_id, trace_id.to_bytes().to_vec());
+
+        let symbol = span.attributes.iter()
+            .find(|attribute| attribute.key == "symbol")
+            .and_then(|attribute| attribute.value.as_ref()?.value.clone());
+        assert_eq!(symbol, Some(Value::StringValue("AAPL".to_string())));
+    }
+}
@@ -2,6 +2,7 @@
 
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
+use utils::cache::CacheSchema;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "company")]
@@ -35,3 +36,8 @@ pub struct Model {
 pub enum Relation {}
 
 impl ActiveModelBehavior for ActiveModel {}
+
+// Cached company details written by other builds are read as misses once this is bumped
+impl CacheSchema for Model {
+    const SCHEMA_VERSION: u32 = 1;
+}
@@ -2,4 +2,7 @@
 
 pub mod prelude;
 
+pub mod api_audit_log;
+pub mod api_key;
 pub mod company;
+pub mod ingestor_checkpoint;
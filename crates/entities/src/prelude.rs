@@ -1,3 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.1
 
+pub use super::api_audit_log::Entity as ApiAuditLog;
+pub use super::api_key::Entity as ApiKey;
 pub use super::company::Entity as Company;
+pub use super::ingestor_checkpoint::Entity as IngestorCheckpoint;
@@ -0,0 +1,87 @@
+//! Compares the cache hit path of the two cache formats, decoding a cached model and mapping it
+//! to a response against decoding a pre-encoded response.
+//!
+//! ```bash
+//! cargo bench --package grpc --bench cache_hit
+//! ```
+
+use chrono::NaiveDate;
+use criterion::{criterion_group, criterion_main, Criterion};
+use entities::company::Model;
+use grpc::asset_details::asset_details::asset_details_server::AssetDetails;
+use grpc::asset_details::asset_details::AssetDetailsRequest;
+use grpc::asset_details::{company_response, AssetDetailsService, CacheFormat};
+use prost::Message;
+use rust_decimal_macros::dec;
+use sea_orm::DatabaseConnection;
+use std::sync::Arc;
+use utils::cache::{set_cache, Cache, CachePolicy, InMemoryCache};
+
+const SYMBOL: &str = "BENCH";
+
+fn company() -> Model {
+    Model {
+        id: uuid::Uuid::now_v7(),
+        symbol: SYMBOL.to_string(),
+        name: "Benchmark Corporation".to_string(),
+        address: Some("1 Benchmark Way".to_string()),
+        city: Some("Springfield".to_string()),
+        state: Some("IL".to_string()),
+        zip: Some("62701".to_string()),
+        icon_url: Some("https://cdn.example.com/bench/icon.png".to_string()),
+        logo_url: Some("https://cdn.example.com/bench/logo.svg".to_string()),
+        cik: Some("0000000001".to_string()),
+        description: Some("Benchmark Corporation designs, manufactures and markets benchmarks. ".repeat(12)),
+        homepage_url: Some("https://bench.example.com".to_string()),
+        list_date: NaiveDate::from_ymd_opt(1999, 11, 18),
+        market_cap: Some(dec!(123456789012.34)),
+        phone_number: Some("(555) 555-0100".to_string()),
+        primary_exchange_id: Some("XNAS".to_string()),
+        primary_exchange_name: Some("Nasdaq".to_string()),
+        sic_code: Some("3571".to_string()),
+        sic_description: Some("ELECTRONIC COMPUTERS".to_string()),
+        total_employees: Some(150000),
+        weighted_shares_outstanding: Some(15000000000),
+    }
+}
+
+/// A service whose cache already holds the company in the given format. The database is never
+/// reached on a hit, so it is left disconnected.
+async fn warm_service(cache_format: CacheFormat) -> AssetDetailsService {
+    let cache: Arc<dyn Cache> = Arc::new(InMemoryCache::new(16).unwrap());
+    let cache_policy = CachePolicy::default();
+    let key = cache_format.key(SYMBOL);
+
+    match cache_format {
+        CacheFormat::Model => {
+            set_cache(cache.as_ref(), &key, &company(), cache_policy.ttl, &cache_policy.codec).await.unwrap();
+        }
+        CacheFormat::Response => {
+            cache.set(&key, company_response(company()).encode_to_vec(), cache_policy.ttl).await.unwrap();
+        }
+    }
+
+    AssetDetailsService::new(DatabaseConnection::Disconnected, cache, cache_policy, cache_format)
+}
+
+fn cache_hit(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("get_company_cache_hit");
+
+    for (name, cache_format) in [("model", CacheFormat::Model), ("response", CacheFormat::Response)] {
+        let service = runtime.block_on(warm_service(cache_format));
+
+        group.bench_function(name, |b| {
+            b.to_async(&runtime).iter(|| async {
+                let request = tonic::Request::new(AssetDetailsRequest { symbol: SYMBOL.to_string() });
+
+                service.get_company(request).await.unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, cache_hit);
+criterion_main!(benches);
@@ -1,4 +1,6 @@
+use std::env;
 use std::fs;
+use std::path::PathBuf;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let dir_path = "./protobufs";
@@ -8,8 +10,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .filter(|path| path.ends_with(".proto"))
         .collect();
 
+    // Every proto's descriptors in one set, served by the API's reflection service
+    let descriptor_path = PathBuf::from(env::var("OUT_DIR")?).join("protobufs_descriptor.bin");
+
     tonic_build::configure()
         .build_server(true)
+        .file_descriptor_set_path(descriptor_path)
         .compile_protos(&proto_files, &[dir_path])?;
 
     Ok(())
@@ -0,0 +1,113 @@
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use tonic::{Response, Status};
+use entities::api_audit_log;
+use crate::api_audit::api_audit::{AuditLogEntry, AuditLogQueryRequest, AuditLogQueryResponse};
+use crate::api_audit::api_audit::api_audit_server::ApiAudit;
+use crate::authentication::Principal;
+
+pub mod api_audit {
+    tonic::include_proto!("api_audit");
+}
+
+/// Permission a caller needs to query the audit log
+pub const AUDIT_READ_PERMISSION: &str = "audit:read";
+
+/// Upper bound on the number of entries returned by a single query
+const MAX_QUERY_LIMIT: u64 = 1000;
+
+/// Parse an RFC 3339 timestamp from a query request
+///
+/// # Arguments
+///
+/// * `value` - The raw timestamp
+///
+/// # Returns
+///
+/// The parsed timestamp in UTC
+///
+/// # Errors
+///
+/// * If the timestamp is not valid RFC 3339, returns a chrono ParseError
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(value).map(|value| value.with_timezone(&Utc))
+}
+
+#[derive(Debug)]
+pub struct ApiAuditService {
+    pub database_connection: DatabaseConnection,
+}
+
+#[tonic::async_trait]
+impl ApiAudit for ApiAuditService {
+    async fn query_audit_log(
+        &self,
+        request: tonic::Request<AuditLogQueryRequest>,
+    ) -> Result<Response<AuditLogQueryResponse>, Status> {
+        let is_admin = request.extensions()
+            .get::<Principal>()
+            .map(|principal| principal.permissions.iter().any(|permission| permission == AUDIT_READ_PERMISSION))
+            .unwrap_or(false);
+
+        if !is_admin {
+            return Err(Status::permission_denied("Querying the audit log requires the audit:read permission"));
+        }
+
+        let query = request.into_inner();
+
+        let start = parse_timestamp(&query.start)
+            .map_err(|e| Status::invalid_argument(format!("Invalid start: {}", e)))?;
+        let end = parse_timestamp(&query.end)
+            .map_err(|e| Status::invalid_argument(format!("Invalid end: {}", e)))?;
+
+        if end <= start {
+            return Err(Status::invalid_argument("end must be after start"));
+        }
+
+        let limit: u64 = match query.limit {
+            limit if limit <= 0 => MAX_QUERY_LIMIT,
+            limit => (limit as u64).min(MAX_QUERY_LIMIT),
+        };
+
+        // Filtering on created_at first lets Postgres prune partitions outside the range
+        let mut select = api_audit_log::Entity::find()
+            .filter(api_audit_log::Column::CreatedAt.gte(start))
+            .filter(api_audit_log::Column::CreatedAt.lt(end));
+
+        if let Some(principal) = query.principal {
+            select = select.filter(api_audit_log::Column::Principal.eq(principal));
+        }
+
+        if let Some(method) = query.method {
+            select = select.filter(api_audit_log::Column::Method.eq(method));
+        }
+
+        if let Some(symbol) = query.symbol {
+            select = select.filter(Expr::cust_with_values("request_params->>'symbol' = $1", [symbol]));
+        }
+
+        let records = select
+            .order_by_desc(api_audit_log::Column::CreatedAt)
+            .limit(limit)
+            .all(&self.database_connection)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to query audit log: {}", e);
+                Status::internal("Failed to query audit log")
+            })?;
+
+        let entries: Vec<AuditLogEntry> = records.into_iter().map(|record| AuditLogEntry {
+            id: record.id.to_string(),
+            created_at: record.created_at.to_rfc3339(),
+            principal: record.principal,
+            auth_method: record.auth_method,
+            method: record.method,
+            request_params: record.request_params.to_string(),
+            status_code: record.status_code,
+            latency_us: record.latency_us,
+        }).collect();
+
+        Ok(Response::new(AuditLogQueryResponse { entries }))
+    }
+}
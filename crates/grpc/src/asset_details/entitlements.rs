@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+use crate::asset_details::asset_details::AssetDetailsCompanyResponse;
+use crate::authentication::Principal;
+use utils::error::{Error, ErrorType};
+
+/// Optional response fields that can be restricted. `id`, `symbol` and `name` identify the company
+/// and are always visible.
+pub const RESTRICTABLE_FIELDS: [&str; 18] = [
+    "address",
+    "city",
+    "state",
+    "zip",
+    "icon_url",
+    "logo_url",
+    "cik",
+    "description",
+    "homepage_url",
+    "list_date",
+    "market_cap",
+    "phone_number",
+    "primary_exchange_id",
+    "primary_exchange_name",
+    "sic_code",
+    "sic_description",
+    "total_employees",
+    "weighted_shares_outstanding",
+];
+
+/// Clear a field on the response, returning whether it held a value
+fn clear_field(response: &mut AssetDetailsCompanyResponse, field: &str) -> bool {
+    match field {
+        "address" => response.address.take().is_some(),
+        "city" => response.city.take().is_some(),
+        "state" => response.state.take().is_some(),
+        "zip" => response.zip.take().is_some(),
+        "icon_url" => response.icon_url.take().is_some(),
+        "logo_url" => response.logo_url.take().is_some(),
+        "cik" => response.cik.take().is_some(),
+        "description" => response.description.take().is_some(),
+        "homepage_url" => response.homepage_url.take().is_some(),
+        "list_date" => response.list_date.take().is_some(),
+        "market_cap" => response.market_cap.take().is_some(),
+        "phone_number" => response.phone_number.take().is_some(),
+        "primary_exchange_id" => response.primary_exchange_id.take().is_some(),
+        "primary_exchange_name" => response.primary_exchange_name.take().is_some(),
+        "sic_code" => response.sic_code.take().is_some(),
+        "sic_description" => response.sic_description.take().is_some(),
+        "total_employees" => response.total_employees.take().is_some(),
+        "weighted_shares_outstanding" => response.weighted_shares_outstanding.take().is_some(),
+        _ => false,
+    }
+}
+
+/// Parse a comma separated list of field names, rejecting anything that can't be restricted
+fn parse_fields(raw_fields: &str) -> Result<HashSet<String>, Error> {
+    raw_fields
+        .split(',')
+        .map(|field| field.trim())
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            if RESTRICTABLE_FIELDS.contains(&field) {
+                Ok(field.to_string())
+            } else {
+                Err(Error::new(ErrorType::InvalidConfig, format!("Field cannot be restricted: {}", field)))
+            }
+        })
+        .collect()
+}
+
+/// Maps the permissions of a verified caller to the restricted response fields they may see.
+///
+/// Fields that are not restricted are visible to everyone. A restricted field is only visible to
+/// callers holding a permission that grants it, and is otherwise cleared from the response before
+/// it is returned, whether the response came from the cache or the database.
+#[derive(Debug, Clone, Default)]
+pub struct EntitlementPolicy {
+    restricted_fields: HashSet<String>,
+    grants: HashMap<String, HashSet<String>>,
+}
+
+impl EntitlementPolicy {
+    /// Build a policy from config values
+    ///
+    /// # Arguments
+    ///
+    /// * `raw_restricted_fields` - Comma separated fields that require a grant, e.g. `description,logo_url`
+    /// * `raw_grants` - Semicolon separated `permission=field,field` rules, e.g.
+    ///   `asset_details:licensed=description,logo_url,icon_url;asset_details:branding=logo_url,icon_url`
+    ///
+    /// # Returns
+    ///
+    /// The entitlement policy, which redacts nothing when no fields are restricted
+    ///
+    /// # Errors
+    ///
+    /// * If a rule is malformed or names an unknown field, returns an InvalidConfig error
+    pub fn from_config(raw_restricted_fields: &str, raw_grants: &str) -> Result<Self, Error> {
+        let restricted_fields = parse_fields(raw_restricted_fields)?;
+
+        let mut grants: HashMap<String, HashSet<String>> = HashMap::new();
+
+        for rule in raw_grants.split(';').map(|rule| rule.trim()).filter(|rule| !rule.is_empty()) {
+            let (permission, fields) = rule.split_once('=').ok_or_else(|| {
+                Error::new(ErrorType::InvalidConfig, format!("Invalid entitlement grant, expected permission=fields: {}", rule))
+            })?;
+
+            grants.entry(permission.trim().to_string())
+                .or_default()
+                .extend(parse_fields(fields)?);
+        }
+
+        Ok(Self {
+            restricted_fields,
+            grants,
+        })
+    }
+
+    /// The restricted fields the given permissions grant access to
+    fn granted_fields(&self, permissions: &[String]) -> HashSet<&str> {
+        permissions.iter()
+            .filter_map(|permission| self.grants.get(permission))
+            .flatten()
+            .map(|field| field.as_str())
+            .collect()
+    }
+
+    /// Redact the fields the caller is not entitled to see
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The response to redact in place
+    /// * `principal` - The verified caller, a missing principal is treated as having no permissions
+    ///
+    /// # Returns
+    ///
+    /// The names of the fields that held a value and were redacted
+    pub fn redact(&self, response: &mut AssetDetailsCompanyResponse, principal: Option<&Principal>) -> Vec<&'static str> {
+        if self.restricted_fields.is_empty() {
+            return vec![];
+        }
+
+        let permissions: &[String] = principal.map(|p| p.permissions.as_slice()).unwrap_or(&[]);
+        let granted = self.granted_fields(permissions);
+
+        let mut redacted: Vec<&'static str> = Vec::new();
+
+        for field in RESTRICTABLE_FIELDS {
+            if !self.restricted_fields.contains(field) || granted.contains(field) {
+                continue;
+            }
+
+            if clear_field(response, field) {
+                redacted.push(field);
+            }
+        }
+
+        for field in &redacted {
+            metrics::counter!("asset_details_redactions_total", "field" => *field).increment(1);
+        }
+
+        redacted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authentication::AuthMethod;
+
+    fn response() -> AssetDetailsCompanyResponse {
+        AssetDetailsCompanyResponse {
+            symbol: "TEST".to_string(),
+            description: Some("A company".to_string()),
+            logo_url: Some("https://example.com/logo.svg".to_string()),
+            icon_url: Some("https://example.com/icon.png".to_string()),
+            city: Some("Somewhere".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn principal(permissions: &[&str]) -> Principal {
+        Principal {
+            subject: "partner".to_string(),
+            method: AuthMethod::Bearer,
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_redacts_restricted_fields_without_grant() {
+        let policy = EntitlementPolicy::from_config(
+            "description,logo_url,icon_url",
+            "asset_details:licensed=description,logo_url,icon_url;asset_details:branding=logo_url,icon_url",
+        ).unwrap();
+
+        let mut partner_response = response();
+        let redacted = policy.redact(&mut partner_response, Some(&principal(&[])));
+        assert_eq!(redacted, vec!["icon_url", "logo_url", "description"]);
+        assert_eq!(partner_response.description, None);
+        assert_eq!(partner_response.city, Some("Somewhere".to_string()));
+
+        let mut branding_response = response();
+        let redacted = policy.redact(&mut branding_response, Some(&principal(&["asset_details:branding"])));
+        assert_eq!(redacted, vec!["description"]);
+        assert!(branding_response.logo_url.is_some());
+
+        let mut licensed_response = response();
+        assert!(policy.redact(&mut licensed_response, Some(&principal(&["asset_details:licensed"]))).is_empty());
+        assert_eq!(licensed_response, response());
+
+        let mut anonymous_response = response();
+        assert_eq!(policy.redact(&mut anonymous_response, None).len(), 3);
+    }
+
+    #[test]
+    fn test_empty_policy_redacts_nothing() {
+        let policy = EntitlementPolicy::from_config("", "").unwrap();
+
+        let mut unrestricted_response = response();
+        assert!(policy.redact(&mut unrestricted_response, None).is_empty());
+        assert_eq!(unrestricted_response, response());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(EntitlementPolicy::from_config("symbol", "").is_err());
+        assert!(EntitlementPolicy::from_config("description", "asset_details:licensed").is_err());
+        assert!(EntitlementPolicy::from_config("description", "asset_details:licensed=unknown").is_err());
+    }
+}
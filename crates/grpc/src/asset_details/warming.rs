@@ -0,0 +1,130 @@
+use futures::StreamExt;
+use sea_orm::sea_query::{NullOrdering, Order};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use entities::company;
+use entities::company::Model;
+use utils::cache::{Cache, CachePolicy};
+use utils::error::{Error, ErrorType};
+use crate::asset_details::{company_response, write_company, CacheFormat};
+
+/// Which companies to preload into the cache
+#[derive(Debug, Clone, PartialEq)]
+pub enum WarmTargets {
+    /// The given number of companies with the largest market cap
+    TopByMarketCap(u64),
+    /// A fixed list of symbols
+    Symbols(Vec<String>),
+}
+
+/// Preloads company details into the cache so a fresh deploy or a flushed Redis doesn't send the
+/// first minutes of traffic to Postgres
+#[derive(Debug, Clone)]
+pub struct CacheWarming {
+    pub targets: WarmTargets,
+    /// The maximum number of cache writes in flight at once
+    pub concurrency: usize,
+}
+
+impl CacheWarming {
+    /// Build the warming settings from config, a symbol list takes precedence over a top N
+    ///
+    /// # Arguments
+    ///
+    /// * `raw_symbols` - A comma separated list of symbols to warm
+    /// * `top_n` - The number of companies to warm by market cap when no symbols are listed
+    /// * `concurrency` - The maximum number of cache writes in flight at once
+    ///
+    /// # Returns
+    ///
+    /// The warming settings, or None if there is nothing to warm
+    pub fn from_config(raw_symbols: &str, top_n: u64, concurrency: usize) -> Option<Self> {
+        let symbols: Vec<String> = raw_symbols
+            .split(',')
+            .map(|symbol| symbol.trim().to_string())
+            .filter(|symbol| !symbol.is_empty())
+            .collect();
+
+        let targets = match (symbols.is_empty(), top_n) {
+            (false, _) => WarmTargets::Symbols(symbols),
+            (true, 0) => return None,
+            (true, top_n) => WarmTargets::TopByMarketCap(top_n),
+        };
+
+        Some(CacheWarming {
+            targets,
+            concurrency: concurrency.max(1),
+        })
+    }
+
+    /// Load the target companies from the database
+    async fn load_companies(&self, database_connection: &DatabaseConnection) -> Result<Vec<Model>, Error> {
+        let query = match &self.targets {
+            WarmTargets::Symbols(symbols) => company::Entity::find()
+                .filter(company::Column::Symbol.is_in(symbols.iter().cloned())),
+            WarmTargets::TopByMarketCap(top_n) => company::Entity::find()
+                .order_by_with_nulls(company::Column::MarketCap, Order::Desc, NullOrdering::Last)
+                .limit(*top_n),
+        };
+
+        query.all(database_connection).await.map_err(|e| {
+            Error::new(ErrorType::DatabaseError, format!("Failed to load companies to warm: {}", e))
+        })
+    }
+
+    /// Write the target companies to the cache
+    ///
+    /// # Arguments
+    ///
+    /// * `database_connection` - The database to load the companies from
+    /// * `cache` - The cache to write them to
+    /// * `cache_policy` - The TTL and codec of the entries
+    /// * `cache_format` - Whether the model or the response is cached
+    ///
+    /// # Returns
+    ///
+    /// The number of companies written, companies that fail to be written are logged and skipped
+    ///
+    /// # Errors
+    ///
+    /// * If the companies cannot be loaded, returns a DatabaseError
+    pub async fn warm(&self, database_connection: &DatabaseConnection, cache: &dyn Cache, cache_policy: &CachePolicy, cache_format: CacheFormat) -> Result<usize, Error> {
+        let companies: Vec<Model> = self.load_companies(database_connection).await?;
+        let total = companies.len();
+
+        tracing::info!("Warming the cache with {} companies", total);
+
+        let warmed: usize = futures::stream::iter(companies)
+            .map(|company| async move {
+                let symbol = company.symbol.clone();
+
+                let response = company_response(company.clone());
+
+                write_company(cache, cache_policy, cache_format, &company, &response).await
+                    .inspect_err(|e| tracing::warn!("Failed to warm the cache for {}: {}", symbol, e))
+                    .is_ok()
+            })
+            .buffer_unordered(self.concurrency)
+            .filter(|written| std::future::ready(*written))
+            .count()
+            .await;
+
+        tracing::info!("Warmed the cache with {} of {} companies", warmed, total);
+
+        Ok(warmed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_config() {
+        assert_eq!(CacheWarming::from_config("", 0, 8).map(|warming| warming.targets), None);
+        assert_eq!(CacheWarming::from_config(" ", 100, 8).unwrap().targets, WarmTargets::TopByMarketCap(100));
+
+        let warming = CacheWarming::from_config("AAPL, MSFT,", 100, 0).unwrap();
+        assert_eq!(warming.targets, WarmTargets::Symbols(vec!["AAPL".to_string(), "MSFT".to_string()]));
+        assert_eq!(warming.concurrency, 1);
+    }
+}
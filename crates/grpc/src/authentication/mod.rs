@@ -2,27 +2,94 @@ use tonic::{Status};
 use tonic::transport::{Channel, Error};
 
 use crate::authentication::authentication::authentication_client::AuthenticationClient;
-use crate::authentication::authentication::VerifyRequest;
+use crate::authentication::authentication::{TokenData, VerifyRequest};
 
 pub mod authentication {
     tonic::include_proto!("authentication");
 }
 
+/// The mechanism that was used to authenticate a caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    Bearer,
+    ApiKey,
+    ClientCertificate,
+}
+
+impl AuthMethod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            AuthMethod::Bearer => "bearer",
+            AuthMethod::ApiKey => "api_key",
+            AuthMethod::ClientCertificate => "client_cert",
+        }
+    }
+}
+
+impl std::fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The authenticated caller of a request. The auth interceptor inserts this into the request
+/// extensions so that handlers can make decisions based on who is calling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub subject: String,
+    pub method: AuthMethod,
+    pub permissions: Vec<String>,
+}
+
+impl Principal {
+    /// Build a principal from the token data returned by the authentication server
+    pub fn from_token_data(token_data: Option<TokenData>) -> Self {
+        match token_data {
+            Some(data) => Principal {
+                subject: data.sub,
+                method: AuthMethod::Bearer,
+                permissions: data.permissions,
+            },
+            None => Principal {
+                subject: "unknown".to_string(),
+                method: AuthMethod::Bearer,
+                permissions: vec![],
+            },
+        }
+    }
+}
+
+/// Check that the authentication server accepts connections, for health checks
+///
+/// # Arguments
+///
+/// * `auth_url` - The URL of the authentication server
+///
+/// # Errors
+///
+/// * If the server cannot be reached, returns the transport error
+pub async fn ping_auth(auth_url: String) -> Result<(), Error> {
+    AuthenticationClient::connect(auth_url).await.map(|_| ())
+}
+
 /// Check the authentication server to verify a token
 /// 
 /// # Arguments
 /// 
 /// * `auth_url` - The URL of the authentication server
 /// * `token` - The token to verify
+/// * `request_id` - The ID of the call being authenticated, forwarded so the auth server's logs
+///   can be tied to it
 /// 
 /// # Returns
 /// 
-/// * If the token is verified, returns Ok(())
+/// * If the token is verified, returns the token data (claims and permissions) when the auth
+///   server provides it
 /// 
 /// # Errors
 /// 
 /// * If the token is not verified, returns a Status error
-pub async fn check_auth(auth_url: String, token: &str) -> Result<(), Status> {
+pub async fn check_auth(auth_url: String, token: &str, request_id: Option<&str>) -> Result<Option<TokenData>, Status> {
     tracing::debug!("Calling auth server at {} to verify token", auth_url);
 
     let open_authentication_client: Result<AuthenticationClient<Channel>, Error> = AuthenticationClient::connect(auth_url).await;
@@ -39,9 +106,18 @@ pub async fn check_auth(auth_url: String, token: &str) -> Result<(), Status> {
     // Notice how the token is passed in the body not a header, this is for the ability to layer
     // authentication mechanisms, so that client_credentials from the service call the
     // authentication service to verify the user or other token it received.
-    let verify_response = authentication_client.verify_token(VerifyRequest {
+    let mut verify_request = tonic::Request::new(VerifyRequest {
         token: token.to_string(),
-    }).await;
+    });
+
+    // Carry the trace on, so the auth server's spans show up under this request
+    crate::trace_context::inject_current_context(verify_request.metadata_mut());
+
+    if let Some(request_id) = request_id {
+        crate::request_id::inject_request_id(verify_request.metadata_mut(), request_id);
+    }
+
+    let verify_response = authentication_client.verify_token(verify_request).await;
 
     match verify_response {
         Ok(data) => {
@@ -53,7 +129,7 @@ pub async fn check_auth(auth_url: String, token: &str) -> Result<(), Status> {
                 let authenticated_status = response.authenticated;
 
                 if authenticated_status {
-                    Ok(())
+                    Ok(response.token_data)
                 } else {
                     Err(Status::unauthenticated("Unauthenticated"))
                 }
@@ -1,2 +1,27 @@
+pub mod api_audit;
 pub mod asset_details;
 pub mod authentication;
+pub mod request_id;
+pub mod trace_context;
+
+/// Encoded descriptors of every proto in `protobufs/`, for gRPC server reflection
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("protobufs_descriptor");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use prost_types::FileDescriptorSet;
+
+    #[test]
+    fn test_descriptor_set_covers_served_services() {
+        let descriptor_set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET).unwrap();
+
+        let services: Vec<String> = descriptor_set.file.iter()
+            .flat_map(|file| file.service.iter().map(move |service| format!("{}.{}", file.package(), service.name())))
+            .collect();
+
+        assert!(services.contains(&asset_details::asset_details::asset_details_server::SERVICE_NAME.to_string()), "{:?}", services);
+        assert!(services.contains(&api_audit::api_audit::api_audit_server::SERVICE_NAME.to_string()), "{:?}", services);
+    }
+}
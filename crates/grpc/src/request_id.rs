@@ -0,0 +1,70 @@
+use tonic::codegen::http::HeaderMap;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use uuid::Uuid;
+
+/// Header carrying the ID that ties a call's logs, its response and the calls it makes together
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller supplied ID that is kept, longer ones are replaced by a generated ID
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The caller's `x-request-id`, or a new UUIDv7 when it sent none. IDs that are too long or
+/// contain anything other than visible ASCII are replaced too, so they are safe to log and to
+/// send on.
+///
+/// # Arguments
+///
+/// * `headers` - The incoming request's headers
+///
+/// # Returns
+///
+/// * The ID the call is known by
+pub fn request_id_from_headers(headers: &HeaderMap) -> String {
+    headers.get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= MAX_REQUEST_ID_LENGTH)
+        .filter(|value| value.bytes().all(|byte| byte.is_ascii_graphic()))
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| Uuid::now_v7().to_string())
+}
+
+/// Add the request ID to the metadata of an outgoing call, so the callee's logs can be tied to
+/// the call that caused it
+///
+/// # Arguments
+///
+/// * `metadata` - The outgoing request's metadata
+/// * `request_id` - The ID of the call being served
+pub fn inject_request_id(metadata: &mut MetadataMap, request_id: &str) {
+    if let Ok(value) = MetadataValue::try_from(request_id) {
+        metadata.insert(REQUEST_ID_HEADER, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::codegen::http::HeaderValue;
+
+    fn headers_with(request_id: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(request_id).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_callers_id_is_kept() {
+        assert_eq!(request_id_from_headers(&headers_with("checkout-7f3a")), "checkout-7f3a");
+    }
+
+    #[test]
+    fn test_missing_or_unusable_ids_are_generated() {
+        let generated = request_id_from_headers(&HeaderMap::new());
+        assert!(Uuid::parse_str(&generated).is_ok());
+
+        for unusable in ["", "has spaces", &"x".repeat(MAX_REQUEST_ID_LENGTH + 1)] {
+            let replaced = request_id_from_headers(&headers_with(unusable));
+            assert!(Uuid::parse_str(&replaced).is_ok(), "{:?} was kept", unusable);
+        }
+    }
+}
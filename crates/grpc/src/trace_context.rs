@@ -0,0 +1,90 @@
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::Context;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use tonic::codegen::http::HeaderMap;
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Reads the W3C `traceparent` and `tracestate` headers of an incoming request
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Writes the W3C trace context into the metadata of an outgoing request
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (MetadataKey::from_bytes(key.as_bytes()), MetadataValue::try_from(&value)) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+fn extract_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+fn inject_context(context: &Context, metadata: &mut MetadataMap) {
+    TraceContextPropagator::new().inject_context(context, &mut MetadataInjector(metadata));
+}
+
+/// Continue the caller's trace, making the span a child of the `traceparent` the request
+/// carries. Requests without one start a new trace.
+///
+/// # Arguments
+///
+/// * `span` - The span covering the request
+/// * `headers` - The incoming request's headers
+pub fn set_parent_from_headers(span: &tracing::Span, headers: &HeaderMap) {
+    span.set_parent(extract_context(headers));
+}
+
+/// Add the current span's `traceparent` to the metadata of an outgoing call, so the callee's
+/// spans join the same trace. Nothing is added when traces are not exported.
+///
+/// # Arguments
+///
+/// * `metadata` - The outgoing request's metadata
+pub fn inject_current_context(metadata: &mut MetadataMap) {
+    inject_context(&tracing::Span::current().context(), metadata);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+    use tonic::codegen::http::HeaderValue;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_traceparent_round_trips() {
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", HeaderValue::from_static(TRACEPARENT));
+
+        let context = extract_context(&headers);
+        assert_eq!(context.span().span_context().trace_id().to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert!(context.span().span_context().is_remote());
+
+        let mut metadata = MetadataMap::new();
+        inject_context(&context, &mut metadata);
+        assert_eq!(metadata.get("traceparent").unwrap(), TRACEPARENT);
+    }
+
+    #[test]
+    fn test_nothing_injected_without_a_trace() {
+        let mut metadata = MetadataMap::new();
+        inject_context(&extract_context(&HeaderMap::new()), &mut metadata);
+
+        assert!(metadata.get("traceparent").is_none());
+    }
+}
@@ -1,29 +1,214 @@
 use sea_orm_migration::prelude::*;
+pub use sea_orm_migration::MigrationStatus;
 
 pub struct Migrator;
 
-use sea_orm::DatabaseConnection;
+use std::sync::{Arc, Mutex};
+use sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbBackend, ProxyDatabaseTrait, ProxyExecResult, ProxyRow, Statement, TransactionTrait};
 
 mod m20240913_000001_company_table;
+mod m20241201_000001_api_key_table;
+mod m20241202_000001_api_audit_log_table;
+mod m20241203_000001_ingestor_checkpoint_table;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
         vec![
             Box::new(m20240913_000001_company_table::Migration),
+            Box::new(m20241201_000001_api_key_table::Migration),
+            Box::new(m20241202_000001_api_audit_log_table::Migration),
+            Box::new(m20241203_000001_ingestor_checkpoint_table::Migration),
         ]
     }
 }
 
-pub async fn run_migrations(database: &DatabaseConnection) {
-    let outcome = Migrator::up(database, None).await;
+/// Key of the Postgres advisory lock held while migrating, so replicas starting together apply
+/// migrations one at a time instead of racing
+const MIGRATION_LOCK_KEY: i64 = 0x6173_7365_745f_6d69;
 
-    match outcome {
-        Ok(_) => {
-            log::info!("Migrations applied successfully");
-        }
-        Err(e) => {
-            log::error!("Error applying migrations: {:?}", e);
+/// Which way a dry run goes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// The name of the latest migration, the schema version this binary expects
+pub fn expected_version() -> String {
+    Migrator::migrations().last().map(|migration| migration.name().to_string()).unwrap_or_default()
+}
+
+/// Apply every pending migration while holding an advisory lock, in a single transaction so a
+/// failed migration leaves the schema untouched
+///
+/// # Arguments
+///
+/// * `database` - The primary database
+///
+/// # Errors
+///
+/// * If a migration fails, returns the database error
+pub async fn run_migrations(database: &DatabaseConnection) -> Result<(), DbErr> {
+    let transaction = database.begin().await?;
+
+    transaction
+        .execute_unprepared(&format!("SELECT pg_advisory_xact_lock({})", MIGRATION_LOCK_KEY))
+        .await?;
+
+    Migrator::up(&transaction, None).await?;
+
+    transaction.commit().await?;
+
+    log::info!("Migrations applied successfully, schema is at {}", expected_version());
+
+    Ok(())
+}
+
+/// Roll back the latest applied migrations
+///
+/// # Arguments
+///
+/// * `database` - The primary database
+/// * `steps` - The number of migrations to roll back
+///
+/// # Errors
+///
+/// * If a migration fails, returns the database error
+pub async fn rollback_migrations(database: &DatabaseConnection, steps: u32) -> Result<(), DbErr> {
+    let transaction = database.begin().await?;
+
+    transaction
+        .execute_unprepared(&format!("SELECT pg_advisory_xact_lock({})", MIGRATION_LOCK_KEY))
+        .await?;
+
+    Migrator::down(&transaction, Some(steps)).await?;
+
+    transaction.commit().await
+}
+
+/// The name and status of every migration, oldest first
+///
+/// # Errors
+///
+/// * If the applied migrations cannot be read, returns the database error
+pub async fn migration_status(database: &DatabaseConnection) -> Result<Vec<(String, MigrationStatus)>, DbErr> {
+    Ok(Migrator::get_migration_with_status(database)
+        .await?
+        .iter()
+        .map(|migration| (migration.name().to_string(), migration.status()))
+        .collect())
+}
+
+/// The names of the migrations not yet applied, oldest first
+///
+/// # Errors
+///
+/// * If the applied migrations cannot be read, returns the database error
+pub async fn pending_migrations(database: &DatabaseConnection) -> Result<Vec<String>, DbErr> {
+    Ok(Migrator::get_pending_migrations(database)
+        .await?
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .collect())
+}
+
+/// Records the SQL of every statement instead of running it
+#[derive(Debug)]
+struct RecordingDatabase {
+    statements: Arc<Mutex<Vec<String>>>,
+}
+
+impl RecordingDatabase {
+    /// A connection that records into `statements`
+    async fn connect(statements: Arc<Mutex<Vec<String>>>) -> Result<DatabaseConnection, DbErr> {
+        let proxy: Arc<Box<dyn ProxyDatabaseTrait>> = Arc::new(Box::new(RecordingDatabase { statements }));
+
+        Database::connect_proxy(DbBackend::Postgres, proxy).await
+    }
+}
+
+#[async_trait::async_trait]
+impl ProxyDatabaseTrait for RecordingDatabase {
+    async fn query(&self, statement: Statement) -> Result<Vec<ProxyRow>, DbErr> {
+        self.statements.lock().map_err(|e| DbErr::Custom(e.to_string()))?.push(statement.to_string());
+        Ok(Vec::new())
+    }
+
+    async fn execute(&self, statement: Statement) -> Result<ProxyExecResult, DbErr> {
+        self.statements.lock().map_err(|e| DbErr::Custom(e.to_string()))?.push(statement.to_string());
+        Ok(ProxyExecResult::default())
+    }
+}
+
+/// The SQL that applying the pending migrations, or rolling back the latest ones, would run,
+/// without running it. Migrations that read the schema to decide what to do are recorded as if
+/// the schema were empty.
+///
+/// # Arguments
+///
+/// * `database` - The primary database, only read to find the applied migrations
+/// * `direction` - Whether to apply or roll back
+/// * `steps` - The number of migrations to roll back, ignored when applying
+///
+/// # Returns
+///
+/// The name of each migration that would run, with its statements
+///
+/// # Errors
+///
+/// * If the applied migrations cannot be read, returns the database error
+pub async fn dry_run(database: &DatabaseConnection, direction: Direction, steps: u32) -> Result<Vec<(String, Vec<String>)>, DbErr> {
+    let statuses: Vec<(String, MigrationStatus)> = migration_status(database).await?;
+
+    let mut selected: Vec<Box<dyn MigrationTrait>> = Migrator::migrations()
+        .into_iter()
+        .zip(statuses)
+        .filter(|(_, (_, status))| match direction {
+            Direction::Up => *status == MigrationStatus::Pending,
+            Direction::Down => *status == MigrationStatus::Applied,
+        })
+        .map(|(migration, _)| migration)
+        .collect();
+
+    if direction == Direction::Down {
+        selected.reverse();
+        selected.truncate(steps as usize);
+    }
+
+    let mut plan: Vec<(String, Vec<String>)> = Vec::with_capacity(selected.len());
+
+    for migration in selected {
+        let statements: Arc<Mutex<Vec<String>>> = Arc::default();
+        let connection = RecordingDatabase::connect(statements.clone()).await?;
+        let manager = SchemaManager::new(&connection);
+
+        match direction {
+            Direction::Up => migration.up(&manager).await?,
+            Direction::Down => migration.down(&manager).await?,
         }
+
+        let statements = statements.lock().map_err(|e| DbErr::Custom(e.to_string()))?.clone();
+        plan.push((migration.name().to_string(), statements));
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recorder_captures_migration_sql() {
+        let statements: Arc<Mutex<Vec<String>>> = Arc::default();
+        let connection = RecordingDatabase::connect(statements.clone()).await.unwrap();
+
+        let migrations = Migrator::migrations();
+        migrations[0].up(&SchemaManager::new(&connection)).await.unwrap();
+
+        let statements = statements.lock().unwrap().clone();
+        assert!(statements.iter().any(|statement| statement.starts_with(r#"CREATE TABLE IF NOT EXISTS "company""#)), "{:?}", statements);
+        assert_eq!(expected_version(), "m20241203_000001_ingestor_checkpoint_table");
     }
 }
@@ -0,0 +1,72 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+
+        // The query builder has no support for declarative partitioning, so the parent table is
+        // created with raw SQL. Monthly partitions are created and dropped by the API at runtime,
+        // the default partition only catches rows written before their month's partition exists and
+        // maintenance moves them into the partition when it creates it.
+        connection
+            .execute_unprepared(
+                r#"
+                CREATE TABLE IF NOT EXISTS api_audit_log (
+                    id uuid NOT NULL,
+                    created_at timestamp with time zone NOT NULL,
+                    principal varchar NOT NULL,
+                    auth_method varchar NOT NULL,
+                    method varchar NOT NULL,
+                    request_params jsonb NOT NULL,
+                    status_code integer NOT NULL,
+                    latency_us bigint NOT NULL,
+                    PRIMARY KEY (id, created_at)
+                ) PARTITION BY RANGE (created_at)
+                "#,
+            )
+            .await?;
+
+        connection
+            .execute_unprepared("CREATE TABLE IF NOT EXISTS api_audit_log_default PARTITION OF api_audit_log DEFAULT")
+            .await?;
+
+        manager
+            .create_index(Index::create()
+                .if_not_exists()
+                .name("idx-api-audit-log-principal")
+                .table(ApiAuditLog::Table)
+                .col(ApiAuditLog::Principal)
+                .col(ApiAuditLog::CreatedAt)
+                .to_owned())
+            .await?;
+
+        manager
+            .create_index(Index::create()
+                .if_not_exists()
+                .name("idx-api-audit-log-created-at")
+                .table(ApiAuditLog::Table)
+                .col(ApiAuditLog::CreatedAt)
+                .to_owned())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Dropping the parent also drops every partition
+        manager
+            .drop_table(Table::drop().table(ApiAuditLog::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub(crate) enum ApiAuditLog {
+    Table,
+    Principal,
+    CreatedAt,
+}
@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(IngestorCheckpoint::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(IngestorCheckpoint::Job).string().not_null().primary_key())
+                    .col(ColumnDef::new(IngestorCheckpoint::LastTicker).string().not_null())
+                    .col(ColumnDef::new(IngestorCheckpoint::Processed).integer().not_null())
+                    .col(ColumnDef::new(IngestorCheckpoint::UpdatedAt).timestamp_with_time_zone().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(IngestorCheckpoint::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub(crate) enum IngestorCheckpoint {
+    Table,
+    Job,
+    LastTicker,
+    Processed,
+    UpdatedAt,
+}
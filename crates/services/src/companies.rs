@@ -1,6 +1,6 @@
 use chrono::NaiveDate;
 use polygon_sdk::models::CompanyDetails;
-use sea_orm::{sea_query, ActiveValue, DatabaseConnection, EntityTrait, InsertResult};
+use sea_orm::{sea_query, ActiveValue, DatabaseConnection, EntityTrait};
 use uuid::Uuid;
 use utils::error::{Error, ErrorType};
 use rust_decimal::Decimal;
@@ -64,12 +64,12 @@ impl From<String> for ExchangeCode {
 /// 
 /// # Returns
 /// 
-/// An empty tuple if successful
+/// The company as stored after the upsert
 /// 
 /// # Errors
 /// 
 /// * If the company details cannot be inserted, returns a custom error
-pub async fn find_existing_or_create(database_connection: &DatabaseConnection, company_details: CompanyDetails) -> Result<(), Error> {
+pub async fn find_existing_or_create(database_connection: &DatabaseConnection, company_details: CompanyDetails) -> Result<company::Model, Error> {
     tracing::debug!("Finding existing company or creating new one");
 
     let new_id = Uuid::now_v7().into();
@@ -168,9 +168,9 @@ pub async fn find_existing_or_create(database_connection: &DatabaseConnection, c
         ])
         .to_owned();
 
-    let model: InsertResult<ActiveModel> = company::Entity::insert(entry)
+    let model: company::Model = company::Entity::insert(entry)
         .on_conflict(conflict_statement)
-        .exec(database_connection)
+        .exec_with_returning(database_connection)
         .await
         .map_err(|e| {
             tracing::error!("Failed to insert company details: {}", e);
@@ -179,5 +179,5 @@ pub async fn find_existing_or_create(database_connection: &DatabaseConnection, c
 
     tracing::debug!("Inserted company details: {:?}", model);
 
-    Ok(())
+    Ok(model)
 }
@@ -0,0 +1,251 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use crate::cache::Cache;
+use crate::error::{Error, ErrorType};
+
+/// When the circuit breaker opens and how long it stays open
+#[derive(Debug, Clone)]
+pub struct BreakerSettings {
+    /// Consecutive failed calls, including timeouts, that open the circuit
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a single probe call is let through
+    pub open_duration: Duration,
+}
+
+impl Default for BreakerSettings {
+    fn default() -> Self {
+        BreakerSettings {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// A probe call is in flight, if it never reports back (e.g. it was cancelled) another probe
+    /// is let through after the open duration
+    HalfOpen { since: Instant },
+}
+
+/// Wraps a cache that can fail (usually Redis) so a failing backend is skipped instead of slowing
+/// down every request.
+///
+/// After `failure_threshold` consecutive failures the circuit opens and every call fails straight
+/// away with a CacheUnavailable error, without reaching the backend. Once `open_duration` passes
+/// one probe call is let through, closing the circuit if it succeeds or opening it again if not.
+#[derive(Debug)]
+pub struct CircuitBreakerCache {
+    inner: Arc<dyn Cache>,
+    settings: BreakerSettings,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreakerCache {
+    /// Wrap a cache in a closed circuit breaker
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The cache to protect
+    /// * `settings` - When the circuit opens and for how long
+    pub fn new(inner: Arc<dyn Cache>, settings: BreakerSettings) -> Self {
+        CircuitBreakerCache {
+            inner,
+            settings,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    /// Wrap a cache in a circuit breaker that starts open, for a backend that may not be connected
+    /// yet. The first call probes the backend, closing the circuit if it is already connected.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The cache to protect
+    /// * `settings` - When the circuit opens and for how long
+    pub fn new_open(inner: Arc<dyn Cache>, settings: BreakerSettings) -> Self {
+        metrics::gauge!("cache_circuit_open").set(1.0);
+
+        CircuitBreakerCache {
+            inner,
+            settings,
+            state: Mutex::new(BreakerState::Open { until: Instant::now() }),
+        }
+    }
+
+    /// Whether calls are currently skipped
+    pub fn is_open(&self) -> bool {
+        self.state.lock().is_ok_and(|state| !matches!(*state, BreakerState::Closed { .. }))
+    }
+
+    /// Decide whether a call may reach the backend, moving an expired open circuit to half open
+    fn acquire(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().map_err(|e| Error::new(ErrorType::CacheError, e.to_string()))?;
+        let now = Instant::now();
+
+        match *state {
+            BreakerState::Closed { .. } => Ok(()),
+            BreakerState::Open { until } if now >= until => {
+                tracing::info!("Cache circuit half open, probing the backend");
+                *state = BreakerState::HalfOpen { since: now };
+                Ok(())
+            }
+            BreakerState::HalfOpen { since } if now.duration_since(since) >= self.settings.open_duration => {
+                *state = BreakerState::HalfOpen { since: now };
+                Ok(())
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => {
+                Err(Error::new(ErrorType::CacheUnavailable, "Cache circuit is open".to_string()))
+            }
+        }
+    }
+
+    /// Record the outcome of a call that reached the backend
+    fn record(&self, succeeded: bool) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+
+        if succeeded {
+            if !matches!(*state, BreakerState::Closed { .. }) {
+                metrics::gauge!("cache_circuit_open").set(0.0);
+                tracing::info!("Cache circuit closed");
+            }
+
+            *state = BreakerState::Closed { failures: 0 };
+            return;
+        }
+
+        metrics::counter!("cache_backend_failures_total").increment(1);
+
+        let open = match *state {
+            BreakerState::Closed { failures } if failures + 1 < self.settings.failure_threshold => {
+                *state = BreakerState::Closed { failures: failures + 1 };
+                false
+            }
+            _ => true,
+        };
+
+        if open {
+            metrics::gauge!("cache_circuit_open").set(1.0);
+            tracing::warn!("Cache circuit open for {:?}", self.settings.open_duration);
+            *state = BreakerState::Open { until: Instant::now() + self.settings.open_duration };
+        }
+    }
+
+    async fn call<T>(&self, call: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        self.acquire()?;
+
+        let result = call.await;
+        self.record(result.is_ok());
+
+        result
+    }
+}
+
+#[async_trait]
+impl Cache for CircuitBreakerCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.call(self.inner.get(key)).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<(), Error> {
+        self.call(self.inner.set(key, value, ttl)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.call(self.inner.delete(key)).await
+    }
+
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.call(self.inner.get_many(keys)).await
+    }
+
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
+        self.call(self.inner.ttl(key)).await
+    }
+
+    async fn try_lock(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        self.call(self.inner.try_lock(key, token, ttl)).await
+    }
+
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), Error> {
+        self.call(self.inner.unlock(key, token)).await
+    }
+
+    // Fails fast while the circuit is open, and a successful probe closes it
+    async fn ping(&self) -> Result<(), Error> {
+        self.call(self.inner.ping()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+    /// A cache that fails every call while `failing` is set
+    #[derive(Debug, Default)]
+    struct FlakyCache {
+        failing: AtomicBool,
+        calls: AtomicU64,
+    }
+
+    #[async_trait]
+    impl Cache for FlakyCache {
+        async fn get(&self, _key: &str) -> Result<Option<Vec<u8>>, Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+
+            match self.failing.load(Ordering::SeqCst) {
+                true => Err(Error::new(ErrorType::CacheError, "timed out".to_string())),
+                false => Ok(None),
+            }
+        }
+
+        async fn set(&self, _key: &str, _value: Vec<u8>, _ttl: Duration) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn delete(&self, _key: &str) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+            Ok(vec![None; keys.len()])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_opens_after_failures_and_closes_after_probe() {
+        let backend = Arc::new(FlakyCache::default());
+        let settings = BreakerSettings { failure_threshold: 3, open_duration: Duration::from_millis(50) };
+        let cache = CircuitBreakerCache::new(backend.clone(), settings);
+
+        backend.failing.store(true, Ordering::SeqCst);
+
+        for _ in 0..3 {
+            assert!(matches!(cache.get("a").await.unwrap_err().error_type, ErrorType::CacheError));
+        }
+
+        // Open, calls are skipped without reaching the backend
+        assert!(cache.is_open());
+        assert!(matches!(cache.get("a").await.unwrap_err().error_type, ErrorType::CacheUnavailable));
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 3);
+
+        // A failed probe opens the circuit again
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(matches!(cache.get("a").await.unwrap_err().error_type, ErrorType::CacheError));
+        assert!(matches!(cache.get("a").await.unwrap_err().error_type, ErrorType::CacheUnavailable));
+
+        // A successful probe closes it
+        backend.failing.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(cache.get("a").await.unwrap(), None);
+        assert!(!cache.is_open());
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 5);
+    }
+}
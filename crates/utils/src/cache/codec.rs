@@ -0,0 +1,204 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::error::{Error, ErrorType};
+
+/// First byte of every envelope, picked so it can't start a JSON document (legacy entries) or a
+/// not found marker
+const MAGIC: u8 = 0xCE;
+
+/// Version of the envelope layout itself, bumped if the header ever changes
+const ENVELOPE_VERSION: u8 = 1;
+
+/// Magic, envelope version, four byte schema version and the compression flag
+const HEADER_LENGTH: usize = 7;
+
+/// How the payload of an entry is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    Lz4 = 1,
+}
+
+impl TryFrom<&str> for Compression {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "lz4" => Ok(Compression::Lz4),
+            _ => Err(Error::new(ErrorType::InvalidConfig, format!("Unknown cache compression: {}", value))),
+        }
+    }
+}
+
+/// How values are encoded before they are written to the cache.
+///
+/// Values are MessagePack wrapped in a small header holding the type's [`CacheSchema`] version, so
+/// an entry written by a build with a different shape of the type is read back as a clean miss
+/// instead of failing to parse.
+#[derive(Debug, Clone)]
+pub struct CacheCodec {
+    pub compression: Compression,
+    /// Payloads smaller than this are stored uncompressed, compression rarely pays off for them
+    pub compression_threshold: usize,
+}
+
+impl Default for CacheCodec {
+    fn default() -> Self {
+        CacheCodec {
+            compression: Compression::Lz4,
+            compression_threshold: 512,
+        }
+    }
+}
+
+/// A type stored in the cache with the cache codec
+pub trait CacheSchema: Serialize + DeserializeOwned {
+    /// Written in the header of every entry, entries holding another version are read back as a
+    /// miss. Bump it whenever a field is added, removed, renamed or changes type.
+    const SCHEMA_VERSION: u32;
+}
+
+impl CacheSchema for String {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+impl CacheCodec {
+    /// Encode a value into a versioned envelope
+    ///
+    /// # Errors
+    ///
+    /// * If the value cannot be serialized, returns a ParseError
+    pub fn encode<T: CacheSchema>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        let payload: Vec<u8> = rmp_serde::to_vec(value).map_err(|e| {
+            Error::new(ErrorType::ParseError, format!("Unable to encode cache value: {}", e))
+        })?;
+
+        let (compression, payload) = match self.compression {
+            Compression::Lz4 if payload.len() >= self.compression_threshold => {
+                (Compression::Lz4, lz4_flex::compress_prepend_size(&payload))
+            }
+            _ => (Compression::None, payload),
+        };
+
+        let mut envelope: Vec<u8> = Vec::with_capacity(HEADER_LENGTH + payload.len());
+        envelope.push(MAGIC);
+        envelope.push(ENVELOPE_VERSION);
+        envelope.extend_from_slice(&T::SCHEMA_VERSION.to_be_bytes());
+        envelope.push(compression as u8);
+        envelope.extend_from_slice(&payload);
+
+        Ok(envelope)
+    }
+}
+
+/// Decode a value from a versioned envelope
+///
+/// # Returns
+///
+/// The value, or None if the entry was written in another format or for another version of
+/// the type, which callers should treat as a miss
+///
+/// # Errors
+///
+/// * If the entry claims the current version but cannot be decoded, returns a CacheMiss error, the
+///   entry is rewritten like any other miss
+pub fn decode<T: CacheSchema>(envelope: &[u8]) -> Result<Option<T>, Error> {
+    if envelope.len() < HEADER_LENGTH || envelope[0] != MAGIC || envelope[1] != ENVELOPE_VERSION {
+        return Ok(None);
+    }
+
+    let version = u32::from_be_bytes([envelope[2], envelope[3], envelope[4], envelope[5]]);
+
+    if version != T::SCHEMA_VERSION {
+        return Ok(None);
+    }
+
+    let decode_error = |message: String| Error::new(ErrorType::CacheMiss, message);
+
+    let payload = &envelope[HEADER_LENGTH..];
+
+    let value: T = match envelope[6] {
+        0 => rmp_serde::from_slice(payload),
+        1 => {
+            let decompressed = lz4_flex::decompress_size_prepended(payload)
+                .map_err(|e| decode_error(format!("Unable to decompress cache value: {}", e)))?;
+            rmp_serde::from_slice(&decompressed)
+        }
+        flag => return Err(decode_error(format!("Unknown cache compression flag: {}", flag))),
+    }.map_err(|e| decode_error(format!("Unable to decode cache value: {}", e)))?;
+
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Company {
+        symbol: String,
+        description: Option<String>,
+    }
+
+    impl CacheSchema for Company {
+        const SCHEMA_VERSION: u32 = 1;
+    }
+
+    mod v2 {
+        use super::*;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct Company {
+            pub symbol: String,
+            pub description: Option<String>,
+            pub employees: Option<i32>,
+        }
+
+        impl CacheSchema for Company {
+            const SCHEMA_VERSION: u32 = 2;
+        }
+    }
+
+    fn company(description_length: usize) -> Company {
+        Company {
+            symbol: "TEST".to_string(),
+            description: Some("a".repeat(description_length)),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_with_and_without_compression() {
+        let codec = CacheCodec::default();
+
+        for length in [10, 10_000] {
+            let encoded = codec.encode(&company(length)).unwrap();
+            assert_eq!(encoded[6] == Compression::Lz4 as u8, length >= codec.compression_threshold);
+            assert_eq!(decode::<Company>(&encoded).unwrap(), Some(company(length)));
+        }
+
+        let uncompressed = CacheCodec { compression: Compression::None, ..Default::default() };
+        let encoded = uncompressed.encode(&company(10_000)).unwrap();
+        assert_eq!(encoded[6], Compression::None as u8);
+        assert_eq!(decode::<Company>(&encoded).unwrap(), Some(company(10_000)));
+    }
+
+    #[test]
+    fn test_changed_schema_is_a_miss() {
+        let encoded = CacheCodec::default().encode(&company(10)).unwrap();
+        assert_eq!(decode::<v2::Company>(&encoded).unwrap(), None);
+
+        let legacy_json = serde_json::to_vec(&company(10)).unwrap();
+        assert_eq!(decode::<Company>(&legacy_json).unwrap(), None);
+    }
+
+    #[test]
+    fn test_undecodable_entry_is_a_miss() {
+        let mut encoded = CacheCodec { compression: Compression::None, ..Default::default() }.encode(&company(10)).unwrap();
+        encoded.truncate(HEADER_LENGTH + 2);
+
+        let error = decode::<Company>(&encoded).unwrap_err();
+        assert!(matches!(error.error_type, ErrorType::CacheMiss), "{}", error);
+    }
+}
@@ -0,0 +1,140 @@
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use async_trait::async_trait;
+use crate::cache::Cache;
+use crate::error::{Error, ErrorType};
+
+/// Upper bound on the delay between attempts to connect
+const MAX_CONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// A cache whose backend is connected in the background, retrying with backoff until it succeeds,
+/// so a backend that is down at startup doesn't stop the process from starting. Every call fails
+/// with a CacheUnavailable error until the backend is connected.
+#[derive(Debug, Clone)]
+pub struct DeferredCache {
+    inner: Arc<OnceLock<Arc<dyn Cache>>>,
+}
+
+impl DeferredCache {
+    /// Start connecting the backend in the background
+    ///
+    /// # Arguments
+    ///
+    /// * `connect` - Connects the backend, called again after every failed attempt
+    ///
+    /// # Returns
+    ///
+    /// A cache that is unavailable until `connect` succeeds
+    pub fn spawn<F, Fut>(connect: F) -> Self
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Arc<dyn Cache>, Error>> + Send,
+    {
+        let inner: Arc<OnceLock<Arc<dyn Cache>>> = Arc::new(OnceLock::new());
+        let slot = inner.clone();
+
+        tokio::spawn(async move {
+            let mut delay = Duration::from_secs(1);
+
+            loop {
+                match connect().await {
+                    Ok(cache) => {
+                        tracing::info!("Cache backend connected");
+                        let _ = slot.set(cache);
+                        return;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to connect the cache backend, retrying in {:?}: {}", delay, e);
+                    }
+                }
+
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_CONNECT_DELAY);
+            }
+        });
+
+        DeferredCache { inner }
+    }
+
+    /// Whether the backend is connected
+    pub fn is_connected(&self) -> bool {
+        self.inner.get().is_some()
+    }
+
+    fn backend(&self) -> Result<&Arc<dyn Cache>, Error> {
+        self.inner.get().ok_or_else(|| {
+            Error::new(ErrorType::CacheUnavailable, "Cache backend is not connected yet".to_string())
+        })
+    }
+}
+
+#[async_trait]
+impl Cache for DeferredCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.backend()?.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<(), Error> {
+        self.backend()?.set(key, value, ttl).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.backend()?.delete(key).await
+    }
+
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.backend()?.get_many(keys).await
+    }
+
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
+        self.backend()?.ttl(key).await
+    }
+
+    async fn try_lock(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        self.backend()?.try_lock(key, token, ttl).await
+    }
+
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), Error> {
+        self.backend()?.unlock(key, token).await
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        self.backend()?.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use crate::cache::InMemoryCache;
+
+    #[tokio::test]
+    async fn test_unavailable_until_connected() {
+        let attempts = Arc::new(AtomicU32::new(0));
+
+        let cache = DeferredCache::spawn({
+            let attempts = attempts.clone();
+            move || {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match attempt {
+                        0 => Err(Error::new(ErrorType::CacheError, "connection refused".to_string())),
+                        _ => Ok(Arc::new(InMemoryCache::new(16)?) as Arc<dyn Cache>),
+                    }
+                }
+            }
+        });
+
+        tokio::task::yield_now().await;
+        assert!(!cache.is_connected());
+        assert!(matches!(cache.get("a").await.unwrap_err().error_type, ErrorType::CacheUnavailable));
+
+        // Retried after a second
+        tokio::time::sleep(Duration::from_millis(1200)).await;
+        assert!(cache.is_connected());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.get("a").await.unwrap(), None);
+    }
+}
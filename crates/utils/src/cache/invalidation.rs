@@ -0,0 +1,188 @@
+use std::sync::Arc;
+use std::time::Duration;
+use futures::StreamExt;
+use redis::Client;
+use tokio::task::JoinHandle;
+use crate::cache::{Cache, RedisCache, RedisTarget};
+use crate::error::{Error, ErrorType};
+
+/// Redis channel carrying the keys deleted or rewritten in the shared cache, as a JSON array
+pub const INVALIDATION_CHANNEL: &str = "cache:invalidations";
+
+/// Upper bound on the delay between attempts to resubscribe
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+/// Collects the cache keys changed during a run and invalidates them in batches, so replicas stop
+/// serving stale entries without a round trip to Redis per key. Keys can either be deleted, or
+/// rewritten with a fresh value.
+#[derive(Debug)]
+pub struct InvalidationBatch {
+    cache: RedisCache,
+    deletes: Vec<String>,
+    rewrites: Vec<(String, Vec<u8>, Duration)>,
+    batch_size: usize,
+    invalidated: usize,
+}
+
+impl InvalidationBatch {
+    /// Create an empty batch
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The shared cache to invalidate keys in
+    /// * `batch_size` - The number of keys collected before they are invalidated together
+    pub fn new(cache: RedisCache, batch_size: usize) -> Self {
+        InvalidationBatch {
+            cache,
+            deletes: Vec::with_capacity(batch_size),
+            rewrites: Vec::new(),
+            batch_size: batch_size.max(1),
+            invalidated: 0,
+        }
+    }
+
+    fn pending(&self) -> usize {
+        self.deletes.len() + self.rewrites.len()
+    }
+
+    /// Add a key to delete, invalidating the batch once it is full
+    ///
+    /// # Errors
+    ///
+    /// * If the batch cannot be invalidated, returns a CacheError. The keys are dropped either way,
+    ///   their entries still expire on their own.
+    pub async fn push(&mut self, key: String) -> Result<(), Error> {
+        self.deletes.push(key);
+
+        if self.pending() >= self.batch_size {
+            return self.flush().await;
+        }
+
+        Ok(())
+    }
+
+    /// Add a key to rewrite with a fresh value, invalidating the batch once it is full
+    ///
+    /// # Errors
+    ///
+    /// * If the batch cannot be invalidated, returns a CacheError
+    pub async fn push_rewrite(&mut self, key: String, value: Vec<u8>, ttl: Duration) -> Result<(), Error> {
+        self.rewrites.push((key, value, ttl));
+
+        if self.pending() >= self.batch_size {
+            return self.flush().await;
+        }
+
+        Ok(())
+    }
+
+    /// Write every pending rewrite, delete every pending delete, and publish all of their keys
+    ///
+    /// # Errors
+    ///
+    /// * If the keys cannot be written, deleted or published, returns a CacheError
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        if self.pending() == 0 {
+            return Ok(());
+        }
+
+        let deletes: Vec<String> = std::mem::take(&mut self.deletes);
+        let rewrites: Vec<(String, Vec<u8>, Duration)> = std::mem::take(&mut self.rewrites);
+
+        let mut rewritten: Vec<String> = Vec::with_capacity(rewrites.len());
+
+        for (key, value, ttl) in rewrites {
+            self.cache.set(&key, value, ttl).await?;
+            rewritten.push(key);
+        }
+
+        self.cache.invalidate(&deletes).await?;
+        self.cache.publish_invalidation(&rewritten).await?;
+
+        let batch_length = deletes.len() + rewritten.len();
+        self.invalidated += batch_length;
+
+        tracing::info!("Invalidated {} cache keys ({} this run)", batch_length, self.invalidated);
+
+        Ok(())
+    }
+
+    /// The number of keys invalidated so far
+    pub fn invalidated(&self) -> usize {
+        self.invalidated
+    }
+}
+
+/// Subscribe to invalidations published by other processes and delete the keys from a cache local
+/// to this process. Resubscribes with backoff if the subscription drops, invalidations published
+/// while disconnected are missed, so local entries should have a short TTL.
+///
+/// # Arguments
+///
+/// * `target` - The Redis to subscribe to, pub/sub needs its own connection
+/// * `local_cache` - The cache to delete invalidated keys from
+///
+/// # Returns
+///
+/// The handle of the background task
+pub fn spawn_invalidation_listener(target: RedisTarget, local_cache: Arc<dyn Cache>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut delay = Duration::from_secs(1);
+
+        loop {
+            // Resolved on every attempt, the Sentinel primary may have moved or not be up yet
+            let subscription = match target.pubsub_client().await {
+                Ok(client) => listen(&client, local_cache.as_ref()).await,
+                Err(e) => Err(e),
+            };
+
+            match subscription {
+                Ok(()) => {
+                    tracing::warn!("Cache invalidation subscription closed, resubscribing");
+                    delay = Duration::from_secs(1);
+                }
+                Err(e) => {
+                    tracing::error!("Cache invalidation subscription failed: {}", e);
+                }
+            }
+
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RESUBSCRIBE_DELAY);
+        }
+    })
+}
+
+async fn listen(client: &Client, local_cache: &dyn Cache) -> Result<(), Error> {
+    let cache_error = |e: redis::RedisError| Error::new(ErrorType::CacheError, e.to_string());
+
+    let mut pubsub = client.get_async_pubsub().await.map_err(cache_error)?;
+    pubsub.subscribe(INVALIDATION_CHANNEL).await.map_err(cache_error)?;
+
+    tracing::info!("Subscribed to cache invalidations on {}", INVALIDATION_CHANNEL);
+
+    let mut messages = pubsub.on_message();
+
+    while let Some(message) = messages.next().await {
+        let keys: Vec<String> = match message.get_payload::<String>().map(|payload| serde_json::from_str(&payload)) {
+            Ok(Ok(keys)) => keys,
+            Ok(Err(e)) => {
+                tracing::error!("Invalid cache invalidation message: {}", e);
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("Invalid cache invalidation message: {}", e);
+                continue;
+            }
+        };
+
+        for key in &keys {
+            if let Err(e) = local_cache.delete(key).await {
+                tracing::error!("Failed to invalidate local cache entry {}: {}", key, e);
+            }
+        }
+
+        tracing::debug!("Invalidated {} local cache entries", keys.len());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use lru::LruCache;
+use crate::cache::Cache;
+use crate::error::{Error, ErrorType};
+
+#[derive(Debug)]
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Instant,
+}
+
+/// In-process cache with a fixed capacity, evicting the least recently used entry when full and
+/// lazily dropping expired entries when they are read. Entries are not shared between replicas.
+#[derive(Debug)]
+pub struct InMemoryCache {
+    entries: Mutex<LruCache<String, Entry>>,
+}
+
+impl InMemoryCache {
+    /// Create an empty cache
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of entries to hold
+    ///
+    /// # Returns
+    ///
+    /// The in-memory cache
+    ///
+    /// # Errors
+    ///
+    /// * If the capacity is zero, returns an InvalidConfig error
+    pub fn new(capacity: usize) -> Result<Self, Error> {
+        let capacity = NonZeroUsize::new(capacity).ok_or_else(|| {
+            Error::new(ErrorType::InvalidConfig, "In-memory cache capacity must be greater than zero".to_string())
+        })?;
+
+        Ok(InMemoryCache {
+            entries: Mutex::new(LruCache::new(capacity)),
+        })
+    }
+
+    /// Read a key while already holding the lock, dropping it if it has expired
+    fn get_locked(entries: &mut LruCache<String, Entry>, key: &str, now: Instant) -> Option<Vec<u8>> {
+        match entries.get(key) {
+            Some(entry) if entry.expires_at > now => Some(entry.value.clone()),
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, LruCache<String, Entry>>, Error> {
+        self.entries.lock().map_err(|e| Error::new(ErrorType::CacheError, e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Cache for InMemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let mut entries = self.lock()?;
+
+        Ok(Self::get_locked(&mut entries, key, Instant::now()))
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<(), Error> {
+        let entry = Entry {
+            value,
+            expires_at: Instant::now() + ttl,
+        };
+
+        self.lock()?.put(key.to_string(), entry);
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.lock()?.pop(key);
+
+        Ok(())
+    }
+
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let mut entries = self.lock()?;
+        let now = Instant::now();
+
+        Ok(keys.iter().map(|key| Self::get_locked(&mut entries, key, now)).collect())
+    }
+
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
+        let entries = self.lock()?;
+
+        Ok(entries.peek(key).and_then(|entry| entry.expires_at.checked_duration_since(Instant::now())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_set_get_delete() {
+        let cache = InMemoryCache::new(10).unwrap();
+
+        cache.set("a", b"1".to_vec(), Duration::from_secs(60)).await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), Some(b"1".to_vec()));
+        assert!(cache.ttl("a").await.unwrap().is_some_and(|ttl| ttl <= Duration::from_secs(60)));
+
+        cache.delete("a").await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), None);
+        assert_eq!(cache.ttl("a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_expiry_and_eviction() {
+        let cache = InMemoryCache::new(2).unwrap();
+
+        cache.set("expired", b"1".to_vec(), Duration::ZERO).await.unwrap();
+        assert_eq!(cache.get("expired").await.unwrap(), None);
+
+        cache.set("a", b"1".to_vec(), Duration::from_secs(60)).await.unwrap();
+        cache.set("b", b"2".to_vec(), Duration::from_secs(60)).await.unwrap();
+        cache.get("a").await.unwrap();
+        cache.set("c", b"3".to_vec(), Duration::from_secs(60)).await.unwrap();
+
+        let keys = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        assert_eq!(cache.get_many(&keys).await.unwrap(), vec![Some(b"1".to_vec()), None, Some(b"3".to_vec())]);
+
+        assert!(InMemoryCache::new(0).is_err());
+    }
+}
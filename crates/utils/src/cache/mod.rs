@@ -0,0 +1,328 @@
+use std::fmt::Debug;
+use std::time::Duration;
+use async_trait::async_trait;
+use rand::Rng;
+use crate::error::{Error, ErrorType};
+
+pub mod circuit_breaker;
+pub mod codec;
+pub mod deferred;
+pub mod invalidation;
+pub mod memory;
+pub mod redis_cache;
+pub mod single_flight;
+pub mod tiered;
+
+pub use circuit_breaker::{BreakerSettings, CircuitBreakerCache};
+pub use codec::{CacheCodec, CacheSchema, Compression};
+pub use deferred::DeferredCache;
+pub use invalidation::{spawn_invalidation_listener, InvalidationBatch};
+pub use memory::InMemoryCache;
+pub use redis_cache::{parse_redis_target, CacheSettings, RedisCache, RedisTarget, RedisTopology};
+pub use single_flight::SingleFlight;
+pub use tiered::TieredCache;
+
+/// A key value cache with per-entry expiry. Values are raw bytes, use [`check_cache`] and
+/// [`set_cache`] to store typed values.
+///
+/// Implementations are shared between request handlers behind an `Arc<dyn Cache>`, so callers
+/// never need to know which backend is configured.
+#[async_trait]
+pub trait Cache: Send + Sync + Debug {
+    /// Get the value of a key, returning None if it does not exist or has expired
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Set the value of a key, expiring after the given TTL
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<(), Error>;
+
+    /// Remove a key, removing a key that does not exist is not an error
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+
+    /// Get the values of several keys at once, in the same order as the keys
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>, Error>;
+
+    /// Get the time left before a key expires, returning None if it does not exist or has no expiry.
+    /// Backends that can't tell always return None.
+    async fn ttl(&self, _key: &str) -> Result<Option<Duration>, Error> {
+        Ok(None)
+    }
+
+    /// Try to take a short lock shared by every replica using this cache, held until `unlock` is
+    /// called with the same token or the TTL passes. Backends local to one process have nothing to
+    /// coordinate with and always grant the lock.
+    async fn try_lock(&self, _key: &str, _token: &str, _ttl: Duration) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    /// Release a lock taken with `try_lock`, only if it is still held with the same token
+    async fn unlock(&self, _key: &str, _token: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Check that the backend can be reached, for health checks. Backends local to one process
+    /// are always reachable.
+    async fn ping(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Stored in place of a value to record that the value does not exist. It can never be mistaken for
+/// an encoded value, which always starts with the codec's header.
+pub const NOT_FOUND_MARKER: &[u8] = b"\x00not_found";
+
+/// A value read from the cache, either the value itself or a record that it does not exist
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheEntry<T> {
+    Found(T),
+    NotFound,
+}
+
+impl<T> CacheEntry<T> {
+    /// Map a found value, keeping a not found marker as is
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> CacheEntry<U> {
+        match self {
+            CacheEntry::Found(value) => CacheEntry::Found(f(value)),
+            CacheEntry::NotFound => CacheEntry::NotFound,
+        }
+    }
+}
+
+/// The cache key of a company's details, shared by the API and the ingestor
+pub fn company_details_key(symbol: &str) -> String {
+    format!("company_details:{}", symbol)
+}
+
+/// The cache key of a company's pre-encoded response, shared by the API and the ingestor
+pub fn company_response_key(symbol: &str) -> String {
+    format!("company_response:{}", symbol)
+}
+
+/// The cache key of the stale copy kept alongside an entry, served when the entry can't be
+/// recomputed
+pub fn stale_key(key: &str) -> String {
+    format!("stale:{}", key)
+}
+
+/// How entries are written to the cache and recomputed when they expire
+#[derive(Debug, Clone)]
+pub struct CachePolicy {
+    /// Base time to live of an entry
+    pub ttl: Duration,
+    /// Fraction of the TTL, between 0 and 1, randomly added or removed per entry so entries
+    /// written together (e.g. after a bulk ingest) don't all expire together
+    pub ttl_jitter: f64,
+    /// When set, only the replica holding a lock of this TTL recomputes a missing entry while
+    /// the others wait for it to appear in the cache
+    pub recompute_lock_ttl: Option<Duration>,
+    /// Time to live of a not found marker, kept short so new listings show up quickly. Zero
+    /// disables negative caching.
+    pub negative_ttl: Duration,
+    /// How values are encoded
+    pub codec: CacheCodec,
+    /// Time to live of the stale copy written alongside each entry, served when the source is
+    /// unavailable. Zero disables stale copies.
+    pub stale_ttl: Duration,
+    /// Entries read within this long of expiring are refreshed in the background. Zero disables
+    /// refreshing ahead of expiry.
+    pub refresh_ahead: Duration,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        CachePolicy {
+            ttl: Duration::from_secs(60 * 60 * 24 * 30),
+            ttl_jitter: 0.1,
+            recompute_lock_ttl: None,
+            negative_ttl: Duration::from_secs(60),
+            codec: CacheCodec::default(),
+            stale_ttl: Duration::from_secs(60 * 60 * 24 * 7),
+            refresh_ahead: Duration::ZERO,
+        }
+    }
+}
+
+impl CachePolicy {
+    /// The TTL for a new entry, with jitter applied
+    pub fn jittered_ttl(&self) -> Duration {
+        jitter(self.ttl, self.ttl_jitter)
+    }
+}
+
+/// Randomly spread a duration by up to `ratio` of itself in either direction
+///
+/// # Arguments
+///
+/// * `duration` - The base duration
+/// * `ratio` - The maximum spread as a fraction of the duration, clamped to between 0 and 1
+///
+/// # Returns
+///
+/// A duration between `duration * (1 - ratio)` and `duration * (1 + ratio)`
+pub fn jitter(duration: Duration, ratio: f64) -> Duration {
+    let ratio = ratio.clamp(0.0, 1.0);
+
+    if ratio == 0.0 {
+        return duration;
+    }
+
+    let factor: f64 = rand::thread_rng().gen_range((1.0 - ratio)..=(1.0 + ratio));
+
+    duration.mul_f64(factor)
+}
+
+/// Check the cache for a generic key and return the value if it exists
+/// Shared fetch, handle and parse logic for all cache fetch operations
+///
+/// # Arguments
+///
+/// * `cache` - The cache backend
+/// * `key` - The cache key
+///
+/// # Returns
+///
+/// The deserialized value of the cache key, of generic type T
+///
+/// # Errors
+///
+/// * If the cache key does not exist or holds a not found marker, returns a CacheMiss error which
+///   is a custom error, so callers can tell a miss apart from a failure to reach the cache.
+pub async fn check_cache<T: CacheSchema>(cache: &dyn Cache, key: &str) -> Result<T, Error> {
+    match check_cache_entry(cache, key).await? {
+        CacheEntry::Found(value) => Ok(value),
+        CacheEntry::NotFound => Err(Error::new(ErrorType::CacheMiss, format!("Not found marker cached for {}", key))),
+    }
+}
+
+/// Check the cache for a generic key, telling a cached value apart from a cached not found marker
+///
+/// # Arguments
+///
+/// * `cache` - The cache backend
+/// * `key` - The cache key
+///
+/// # Returns
+///
+/// The deserialized value of the cache key, or NotFound if a not found marker is cached
+///
+/// # Errors
+///
+/// * If the cache key does not exist, was written for another version of the type or cannot be
+///   decoded, returns a CacheMiss error
+pub async fn check_cache_entry<T: CacheSchema>(cache: &dyn Cache, key: &str) -> Result<CacheEntry<T>, Error> {
+    let cached_data: Vec<u8> = match check_cache_raw(cache, key).await? {
+        CacheEntry::Found(cached_data) => cached_data,
+        CacheEntry::NotFound => return Ok(CacheEntry::NotFound),
+    };
+
+    let parse_data: T = codec::decode(&cached_data)
+        .inspect_err(|e| tracing::debug!("Treating undecodable cache entry for {} as a miss: {}", key, e))?
+        .ok_or_else(|| Error::new(ErrorType::CacheMiss, format!("Cache entry for {} has an outdated schema", key)))?;
+
+    Ok(CacheEntry::Found(parse_data))
+}
+
+/// Check the cache for a key holding bytes that are not encoded with the cache codec, such as
+/// pre-encoded responses
+///
+/// # Arguments
+///
+/// * `cache` - The cache backend
+/// * `key` - The cache key
+///
+/// # Returns
+///
+/// The raw bytes of the cache key, or NotFound if a not found marker is cached
+///
+/// # Errors
+///
+/// * If the cache key does not exist, returns a CacheMiss error
+pub async fn check_cache_raw(cache: &dyn Cache, key: &str) -> Result<CacheEntry<Vec<u8>>, Error> {
+    tracing::debug!("{}", format!("Checking cache for {}", key));
+
+    let cached_data: Vec<u8> = cache.get(key).await?.ok_or_else(|| {
+        Error::new(ErrorType::CacheMiss, format!("No cache entry for {}", key))
+    })?;
+
+    if cached_data == NOT_FOUND_MARKER {
+        return Ok(CacheEntry::NotFound);
+    }
+
+    Ok(CacheEntry::Found(cached_data))
+}
+
+/// Record in the cache that a key has no value, so lookups can skip the source until it expires
+///
+/// # Arguments
+///
+/// * `cache` - The cache backend
+/// * `key` - The cache key
+/// * `ttl` - How long to remember that the value does not exist
+///
+/// # Errors
+///
+/// * If the cache set operation fails, returns a CacheError
+pub async fn set_not_found(cache: &dyn Cache, key: &str, ttl: Duration) -> Result<(), Error> {
+    tracing::debug!("{}", format!("Setting not found marker for {}", key));
+
+    cache.set(key, NOT_FOUND_MARKER.to_vec(), ttl).await
+}
+
+/// Set the cache for a generic key with a value
+/// Shared set logic for all cache set operations
+///
+/// # Arguments
+///
+/// * `cache` - The cache backend
+/// * `key` - The cache key
+/// * `data` - The data to cache
+/// * `ttl` - How long the entry lives
+/// * `codec` - How the data is encoded
+///
+/// # Returns
+///
+/// A Result with an empty tuple if successful
+///
+/// # Errors
+///
+/// * If the cache set operation fails, returns a CacheError which is a custom error with details
+pub async fn set_cache<T: CacheSchema>(cache: &dyn Cache, key: &str, data: &T, ttl: Duration, codec: &CacheCodec) -> Result<(), Error> {
+    tracing::debug!("{}", format!("Setting cache for {}", key));
+
+    let encoded_data: Vec<u8> = codec.encode(data)
+        .inspect_err(|_| tracing::error!("Unable to serialize data for {}", key))?;
+
+    cache.set(key, encoded_data, ttl).await?;
+
+    tracing::debug!("{}", format!("Successfully set cache for {}", key));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter_stays_in_range() {
+        let base = Duration::from_secs(1000);
+
+        for _ in 0..100 {
+            let jittered = jitter(base, 0.1);
+            assert!(jittered >= Duration::from_secs(900) && jittered <= Duration::from_secs(1100));
+        }
+
+        assert_eq!(jitter(base, 0.0), base);
+    }
+
+    #[tokio::test]
+    async fn test_not_found_marker() {
+        let cache = InMemoryCache::new(10).unwrap();
+
+        set_not_found(&cache, "missing", Duration::from_secs(60)).await.unwrap();
+        assert_eq!(check_cache_entry::<String>(&cache, "missing").await.unwrap(), CacheEntry::NotFound);
+        assert!(matches!(check_cache::<String>(&cache, "missing").await.unwrap_err().error_type, ErrorType::CacheMiss));
+
+        set_cache(&cache, "found", &"value".to_string(), Duration::from_secs(60), &CacheCodec::default()).await.unwrap();
+        assert_eq!(check_cache_entry::<String>(&cache, "found").await.unwrap(), CacheEntry::Found("value".to_string()));
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Coalesces concurrent calls for the same key so only one runs at a time per process, every other
+/// caller waits for it and receives a clone of its result.
+///
+/// If the running call is cancelled (e.g. the client disconnects), one of the waiters takes over
+/// instead of every waiter failing. Results are not kept once the call completes, caching them is
+/// left to the caller.
+pub struct SingleFlight<T> {
+    calls: Mutex<HashMap<String, Arc<OnceCell<T>>>>,
+}
+
+impl<T> Debug for SingleFlight<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let in_flight = self.calls.lock().map(|calls| calls.len()).unwrap_or_default();
+
+        f.debug_struct("SingleFlight").field("in_flight", &in_flight).finish()
+    }
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        SingleFlight {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `call` for the key, unless a call for the key is already in flight, in which case wait
+    /// for that call's result instead
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key identifying the call
+    /// * `call` - Produces the future to run, only invoked if this caller ends up running it
+    ///
+    /// # Returns
+    ///
+    /// The result of the call, shared by every caller waiting on the key
+    pub async fn run<F, Fut>(&self, key: &str, call: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let cell = self.cell(key);
+
+        let result = cell.get_or_init(call).await.clone();
+
+        // The first caller to finish retires the call, later callers start a fresh one
+        if let Ok(mut calls) = self.calls.lock() {
+            if calls.get(key).is_some_and(|current| Arc::ptr_eq(current, &cell)) {
+                calls.remove(key);
+            }
+        }
+
+        result
+    }
+
+    fn cell(&self, key: &str) -> Arc<OnceCell<T>> {
+        match self.calls.lock() {
+            Ok(mut calls) => calls.entry(key.to_string()).or_default().clone(),
+            // A poisoned map only loses coalescing, never the call itself
+            Err(_) => Arc::new(OnceCell::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_are_coalesced() {
+        let single_flight: Arc<SingleFlight<usize>> = Arc::new(SingleFlight::new());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let callers = (0..10).map(|_| {
+            let single_flight = single_flight.clone();
+            let runs = runs.clone();
+
+            tokio::spawn(async move {
+                single_flight.run("AAPL", || async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    runs.fetch_add(1, Ordering::SeqCst) + 1
+                }).await
+            })
+        }).collect::<Vec<_>>();
+
+        for caller in callers {
+            assert_eq!(caller.await.unwrap(), 1);
+        }
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // Once the call completed, the next call runs again
+        assert_eq!(single_flight.run("AAPL", || async { 2 }).await, 2);
+    }
+}
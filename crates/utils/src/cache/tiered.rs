@@ -0,0 +1,160 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use crate::cache::Cache;
+use crate::error::Error;
+
+/// Two-tier cache, a small fast L1 (usually in-process) in front of a shared L2 (usually Redis).
+///
+/// Reads are served from L1 when possible and fall back to L2, copying L2 hits into L1. Writes go
+/// to L2 first so L1 never holds a value the shared tier rejected. L1 entries live for at most
+/// `l1_ttl`, which bounds how long a replica can serve a value after it changed in L2.
+#[derive(Debug, Clone)]
+pub struct TieredCache {
+    l1: Arc<dyn Cache>,
+    l2: Arc<dyn Cache>,
+    l1_ttl: Duration,
+}
+
+impl TieredCache {
+    /// Create a two-tier cache
+    ///
+    /// # Arguments
+    ///
+    /// * `l1` - The near cache, checked first
+    /// * `l2` - The shared cache, the source of truth for cached values
+    /// * `l1_ttl` - The maximum time an entry is kept in L1
+    ///
+    /// # Returns
+    ///
+    /// The tiered cache
+    pub fn new(l1: Arc<dyn Cache>, l2: Arc<dyn Cache>, l1_ttl: Duration) -> Self {
+        TieredCache { l1, l2, l1_ttl }
+    }
+
+    /// Copy a value read from L2 into L1, a failure only costs an extra L2 read later
+    async fn fill_l1(&self, key: &str, value: &[u8]) {
+        if let Err(e) = self.l1.set(key, value.to_vec(), self.l1_ttl).await {
+            tracing::warn!("Failed to fill L1 cache for {}: {}", key, e);
+        }
+    }
+}
+
+#[async_trait]
+impl Cache for TieredCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self.l1.get(key).await {
+            Ok(Some(value)) => return Ok(Some(value)),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to read L1 cache for {}: {}", key, e),
+        }
+
+        let value = self.l2.get(key).await?;
+
+        if let Some(value) = &value {
+            self.fill_l1(key, value).await;
+        }
+
+        Ok(value)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<(), Error> {
+        self.l2.set(key, value.clone(), ttl).await?;
+        self.l1.set(key, value, ttl.min(self.l1_ttl)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        // Remove from L1 even if L2 fails so this replica stops serving the old value
+        let l1_result = self.l1.delete(key).await;
+        self.l2.delete(key).await?;
+        l1_result
+    }
+
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let mut values = match self.l1.get_many(keys).await {
+            Ok(values) => values,
+            Err(e) => {
+                tracing::warn!("Failed to read L1 cache: {}", e);
+                vec![None; keys.len()]
+            }
+        };
+
+        let missing: Vec<usize> = (0..keys.len()).filter(|index| values[*index].is_none()).collect();
+
+        if missing.is_empty() {
+            return Ok(values);
+        }
+
+        let missing_keys: Vec<String> = missing.iter().map(|index| keys[*index].clone()).collect();
+        let l2_values = self.l2.get_many(&missing_keys).await?;
+
+        for (index, value) in missing.into_iter().zip(l2_values) {
+            if let Some(value) = &value {
+                self.fill_l1(&keys[index], value).await;
+            }
+
+            values[index] = value;
+        }
+
+        Ok(values)
+    }
+
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
+        self.l2.ttl(key).await
+    }
+
+    async fn try_lock(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        self.l2.try_lock(key, token, ttl).await
+    }
+
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), Error> {
+        self.l2.unlock(key, token).await
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        self.l2.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::InMemoryCache;
+
+    fn tiered() -> (Arc<InMemoryCache>, Arc<InMemoryCache>, TieredCache) {
+        let l1 = Arc::new(InMemoryCache::new(10).unwrap());
+        let l2 = Arc::new(InMemoryCache::new(10).unwrap());
+        let cache = TieredCache::new(l1.clone(), l2.clone(), Duration::from_secs(60));
+
+        (l1, l2, cache)
+    }
+
+    #[tokio::test]
+    async fn test_reads_fill_l1_from_l2() {
+        let (l1, l2, cache) = tiered();
+
+        l2.set("a", b"1".to_vec(), Duration::from_secs(60)).await.unwrap();
+        assert_eq!(l1.get("a").await.unwrap(), None);
+
+        assert_eq!(cache.get("a").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(l1.get("a").await.unwrap(), Some(b"1".to_vec()));
+
+        l2.set("b", b"2".to_vec(), Duration::from_secs(60)).await.unwrap();
+        let keys = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        assert_eq!(cache.get_many(&keys).await.unwrap(), vec![Some(b"1".to_vec()), Some(b"2".to_vec()), None]);
+        assert_eq!(l1.get("b").await.unwrap(), Some(b"2".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_writes_and_deletes_reach_both_tiers() {
+        let (l1, l2, cache) = tiered();
+
+        cache.set("a", b"1".to_vec(), Duration::from_secs(600)).await.unwrap();
+        assert_eq!(l1.get("a").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(l2.get("a").await.unwrap(), Some(b"1".to_vec()));
+
+        cache.delete("a").await.unwrap();
+        assert_eq!(l1.get("a").await.unwrap(), None);
+        assert_eq!(l2.get("a").await.unwrap(), None);
+    }
+}
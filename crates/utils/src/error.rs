@@ -13,6 +13,7 @@ pub enum ErrorType {
     GrpcError,
     CacheError,
     CacheMiss,
+    CacheUnavailable,
     UnknownError
 }
 
@@ -11,6 +11,32 @@ mod tests {
     use grpc::asset_details::asset_details;
     use grpc::asset_details::asset_details::asset_details_client::AssetDetailsClient;
 
+    /// Fetch an access token from Kinde using the client credentials flow
+    async fn fetch_client_credentials() -> Result<String, Error> {
+        let oauth_domain = utils::env::get_required_env_var("OAUTH_DOMAIN");
+        let client_id = utils::env::get_required_env_var("OAUTH_CLIENT_ID");
+        let client_secret = utils::env::get_required_env_var("OAUTH_CLIENT_SECRET");
+
+        let auth_client = kinde_sdk::Client::new(oauth_domain);
+
+        let jwt_options = JwtRequestOptions {
+            client_id,
+            client_secret,
+            audience: None,
+        };
+
+        // We have to generate client credentials for service authentication
+        // This is due to how the grpc auth interceptor works, it must have a valid token
+        let client_credentials = auth_client.get_client_credentials(jwt_options)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error: {:?}", e);
+                Error::new(ErrorKind::Other, "Failed to get client credentials")
+            })?;
+
+        Ok(client_credentials.access_token)
+    }
+
     // API Integration test, ensure all components run together and can handle load
     #[tokio::test]
     async fn test_query_company_details() -> Result<(), Error> {
@@ -31,27 +57,15 @@ mod tests {
             tracing::warn!("No .env file found");
         }
 
-        let oauth_domain = utils::env::get_required_env_var("OAUTH_DOMAIN");
-        let client_id = utils::env::get_required_env_var("OAUTH_CLIENT_ID");
-        let client_secret = utils::env::get_required_env_var("OAUTH_CLIENT_SECRET");
-
-        let auth_client = kinde_sdk::Client::new(oauth_domain);
+        // A static token skips the OAuth round trip, e.g. a token from the mock-auth tokens file
+        // so the load test can run fully offline against a local API and mock auth server
+        let static_access_token = utils::env::get_optional_env_var("ACCESS_TOKEN", "".to_string());
 
-        let jwt_options = JwtRequestOptions {
-            client_id,
-            client_secret,
-            audience: None,
+        let access_token: String = match static_access_token.as_str() {
+            "" => fetch_client_credentials().await?,
+            _ => static_access_token,
         };
 
-        // We have to generate client credentials for service authentication
-        // This is due to how the grpc auth interceptor works, it must have a valid token
-        let client_credentials = auth_client.get_client_credentials(jwt_options)
-            .await
-            .map_err(|e| {
-                tracing::error!("Error: {:?}", e);
-                Error::new(ErrorKind::Other, "Failed to get client credentials")
-            })?;
-
         let concurrency = 100;
         let counter = Arc::new(AtomicUsize::new(0));
         let barrier = Arc::new(Barrier::new(concurrency + 1));
@@ -63,7 +77,7 @@ mod tests {
             let barrier = Arc::clone(&barrier);
 
             let api_url: String = utils::env::get_required_env_var("API_URL");
-            let access_token: String = client_credentials.access_token.clone();
+            let access_token: String = access_token.clone();
 
             tasks.push(tokio::spawn(async move {
                 let try_client = AssetDetailsClient::connect(api_url)